@@ -0,0 +1,88 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Benchmark: many small binary messages, owned parse vs. arena parse.
+//
+//  Run with "cargo bench --bench arena --features arena".
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use bumpalo::Bump;
+use llsd::binary;
+use llsd::LLSDValue;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const MESSAGES: usize = 100_000;
+
+/// Time to parse MESSAGES copies of the message.
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..MESSAGES {
+        f();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    //  Something like an object update: a map of strings, numbers, and a short list.
+    let mut map = HashMap::new();
+    let _ = map.insert(
+        "name".to_string(),
+        LLSDValue::String("Primitive".to_string()),
+    );
+    let _ = map.insert(
+        "description".to_string(),
+        LLSDValue::String("A box".to_string()),
+    );
+    let _ = map.insert(
+        "owner".to_string(),
+        LLSDValue::UUID(llsd::uuid::Uuid::nil()),
+    );
+    let _ = map.insert(
+        "position".to_string(),
+        LLSDValue::Array(vec![
+            LLSDValue::Real(128.0),
+            LLSDValue::Real(64.5),
+            LLSDValue::Real(22.25),
+        ]),
+    );
+    let _ = map.insert("flags".to_string(), LLSDValue::Integer(0x1234));
+    let _ = map.insert(
+        "tags".to_string(),
+        LLSDValue::Array(
+            (0..10)
+                .map(|n| LLSDValue::String(format!("tag{}", n)))
+                .collect(),
+        ),
+    );
+    let bytes = binary::to_bytes(&LLSDValue::Map(map)).unwrap();
+    let body = &bytes[binary::LLSDBINARYPREFIX.len()..];
+
+    let owned = time(|| {
+        std::hint::black_box(binary::parse_array(body).unwrap());
+    });
+    let mut arena = Bump::new();
+    let in_arena = time(|| {
+        std::hint::black_box(binary::parse_in(&arena, body).unwrap());
+        arena.reset();
+    });
+    let rate = |d: Duration| MESSAGES as f64 / d.as_secs_f64();
+    println!(
+        "owned  {:>10.3?}  {:>10.0} messages/sec",
+        owned,
+        rate(owned)
+    );
+    println!(
+        "arena  {:>10.3?}  {:>10.0} messages/sec",
+        in_arena,
+        rate(in_arena)
+    );
+    println!(
+        "speedup {:.1}x",
+        owned.as_secs_f64() / in_arena.as_secs_f64()
+    );
+}
@@ -0,0 +1,62 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Benchmark: 1M-element integer array, general path vs. fast path.
+//
+//  Run with "cargo bench --bench integer_array".
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use llsd::binary;
+use llsd::LLSDValue;
+use std::time::{Duration, Instant};
+
+const COUNT: usize = 1_000_000;
+const PASSES: u32 = 10;
+
+/// Run f PASSES times, return average time.
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    let start = Instant::now();
+    for _ in 0..PASSES {
+        std::hint::black_box(f());
+    }
+    start.elapsed() / PASSES
+}
+
+fn report(what: &str, general: Duration, fast: Duration) {
+    println!(
+        "{:<10} general {:>10.3?}  fast {:>10.3?}  speedup {:.1}x",
+        what,
+        general,
+        fast,
+        general.as_secs_f64() / fast.as_secs_f64()
+    );
+}
+
+fn main() {
+    let ints: Vec<i32> = (0..COUNT as i32).map(|n| n.wrapping_mul(7919)).collect();
+    let val = LLSDValue::Array(ints.iter().map(|&n| LLSDValue::Integer(n)).collect());
+    //  One Real at the end forces the general path for the same amount of data.
+    let mut mixed = val.clone();
+    if let LLSDValue::Array(a) = &mut mixed {
+        a[COUNT - 1] = LLSDValue::Real(0.0);
+    }
+    let bytes = binary::to_bytes(&val).unwrap();
+    let body = &bytes[binary::LLSDBINARYPREFIX.len()..];
+    assert_eq!(bytes, binary::to_bytes_integer_array(&ints).unwrap());
+    assert_eq!(ints, binary::parse_integer_array(body).unwrap());
+
+    report(
+        "generate",
+        time(|| binary::to_bytes(&mixed).unwrap()),
+        time(|| binary::to_bytes(&val).unwrap()),
+    );
+    report(
+        "parse",
+        time(|| binary::parse_array(body).unwrap()),
+        time(|| binary::parse_integer_array(body).unwrap()),
+    );
+}
@@ -0,0 +1,94 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Benchmark: parse a large XML document, counting allocations.
+//
+//  Run with "cargo bench --bench xml_parse".
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use llsd::xml;
+use llsd::LLSDValue;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const OBJECTS: usize = 20_000;
+const PASSES: u32 = 10;
+
+/// System allocator, counting allocations.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Run f PASSES times, return average time and allocations.
+fn measure<T>(mut f: impl FnMut() -> T) -> (Duration, usize) {
+    let allocs = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..PASSES {
+        std::hint::black_box(f());
+    }
+    let elapsed = start.elapsed() / PASSES;
+    (
+        elapsed,
+        (ALLOCATIONS.load(Ordering::Relaxed) - allocs) / PASSES as usize,
+    )
+}
+
+fn main() {
+    //  An array of maps, like an object list from a region.
+    let objects: Vec<LLSDValue> = (0..OBJECTS)
+        .map(|n| {
+            [
+                ("id".to_string(), LLSDValue::Integer(n as i32)),
+                (
+                    "name".to_string(),
+                    LLSDValue::String(format!("Object {}", n)),
+                ),
+                (
+                    "position".to_string(),
+                    LLSDValue::Array(vec![
+                        LLSDValue::Real(n as f64 * 0.5),
+                        LLSDValue::Real(128.0),
+                        LLSDValue::Real(22.25),
+                    ]),
+                ),
+                ("phantom".to_string(), LLSDValue::Boolean(n % 2 == 0)),
+            ]
+            .iter()
+            .cloned()
+            .collect()
+        })
+        .collect();
+    let val = LLSDValue::Array(objects);
+    let text = xml::to_xml_string(&val, true).unwrap();
+    assert_eq!(val, xml::parse(&text).unwrap());
+    let (elapsed, allocs) = measure(|| xml::parse(&text).unwrap());
+    println!(
+        "parse {} bytes, {} objects: {:>10.3?}  {} allocations",
+        text.len(),
+        OBJECTS,
+        elapsed,
+        allocs
+    );
+}
@@ -0,0 +1,30 @@
+//
+//  Smoke test for WebAssembly builds.
+//
+//  Uses the core parse and serialize API, so that
+//
+//      cargo build --example wasm_smoke --target wasm32-unknown-unknown
+//
+//  confirms it all compiles for WASM. Also runs natively.
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use llsd::LLSDValue;
+
+fn main() {
+    let val = LLSDValue::Array(vec![
+        LLSDValue::Integer(42),
+        LLSDValue::String("Hello".to_string()),
+        LLSDValue::Date(1138804193.0),
+        LLSDValue::UUID(uuid::Uuid::nil()),
+    ]);
+    let xml = llsd::xml::to_xml_string(&val, false).unwrap();
+    let binary = llsd::binary::to_bytes(&val).unwrap();
+    let notation = llsd::notation::to_bytes(&val).unwrap();
+    assert_eq!(val, LLSDValue::parse(xml.as_bytes()).unwrap());
+    assert_eq!(val, LLSDValue::parse(&binary).unwrap());
+    assert_eq!(val, LLSDValue::parse(&notation).unwrap());
+    println!("{}", xml);
+}
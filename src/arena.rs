@@ -0,0 +1,292 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Arena-allocated binary parse. Optional, with feature "arena".
+//
+//  For servers parsing many short-lived messages. All strings and
+//  containers of a parsed value live in a bumpalo arena, and are freed
+//  together when the arena is reset or dropped.
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use super::{LLSDValue, NestingTooDeep, DEFAULT_MAX_DEPTH};
+use anyhow::{anyhow, Error};
+use bumpalo::collections::Vec as BumpVec;
+use bumpalo::Bump;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// An LLSD value whose strings and children are in an arena.
+/// Maps are key/value slices in input order, and may contain duplicate keys;
+/// as in the owned parse, the last one wins.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LLSDValueArena<'a> {
+    Undefined,
+    Boolean(bool),
+    Real(f64),
+    Integer(i32),
+    UUID(uuid::Uuid),
+    String(&'a str),
+    Date(f64),
+    URI(&'a str),
+    Binary(&'a [u8]),
+    Map(&'a [(&'a str, LLSDValueArena<'a>)]),
+    Array(&'a [LLSDValueArena<'a>]),
+}
+
+impl<'a> LLSDValueArena<'a> {
+    /// Value for a map key. None if not a map or no such key.
+    pub fn get(&self, key: &str) -> Option<&LLSDValueArena<'a>> {
+        match self {
+            LLSDValueArena::Map(m) => m.iter().rev().find(|(k, _)| *k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Copy out of the arena into an owned LLSDValue.
+    pub fn to_llsd(&self) -> LLSDValue {
+        match self {
+            LLSDValueArena::Undefined => LLSDValue::Undefined,
+            LLSDValueArena::Boolean(v) => LLSDValue::Boolean(*v),
+            LLSDValueArena::Real(v) => LLSDValue::Real(*v),
+            LLSDValueArena::Integer(v) => LLSDValue::Integer(*v),
+            LLSDValueArena::UUID(v) => LLSDValue::UUID(*v),
+            LLSDValueArena::String(v) => LLSDValue::String(v.to_string()),
+            LLSDValueArena::Date(v) => LLSDValue::Date(*v),
+            LLSDValueArena::URI(v) => LLSDValue::URI(v.to_string()),
+            LLSDValueArena::Binary(v) => LLSDValue::Binary(v.to_vec()),
+            LLSDValueArena::Map(m) => LLSDValue::Map(
+                m.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_llsd()))
+                    .collect::<HashMap<String, LLSDValue>>(),
+            ),
+            LLSDValueArena::Array(a) => LLSDValue::Array(a.iter().map(|v| v.to_llsd()).collect()),
+        }
+    }
+}
+
+///    Parse LLSD expressed in binary into a tree in the arena. No header.
+///    Error positions are byte offsets into `b`. Nesting is limited to
+///    `DEFAULT_MAX_DEPTH`.
+pub fn parse_in<'a>(arena: &'a Bump, b: &[u8]) -> Result<&'a LLSDValueArena<'a>, Error> {
+    let mut cursor = ArenaCursor {
+        b,
+        pos: 0,
+        depth: 0,
+        arena,
+    };
+    let val = cursor.parse_value()?;
+    Ok(arena.alloc(val))
+}
+
+/// Input position, and where to put the output.
+struct ArenaCursor<'a, 'b> {
+    b: &'b [u8],
+    pos: usize,
+    depth: usize, // maps and arrays open
+    arena: &'a Bump,
+}
+
+impl<'a, 'b> ArenaCursor<'a, 'b> {
+    /// Exactly N bytes, or an error.
+    fn take(&mut self, n: usize) -> Result<&'b [u8], Error> {
+        if self.b.len() - self.pos < n {
+            return Err(llsd_error!(
+                Truncated,
+                "Binary LLSD, unexpected end of data reading {} bytes at position {}",
+                n,
+                self.pos
+            ));
+        }
+        let out = &self.b[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(out)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(<[u8; 4]>::try_from(self.take(4)?)?))
+    }
+
+    fn read_f64(&mut self) -> Result<f64, Error> {
+        Ok(f64::from_be_bytes(<[u8; 8]>::try_from(self.take(8)?)?))
+    }
+
+    /// Length followed by data.
+    fn read_variable(&mut self) -> Result<&'b [u8], Error> {
+        let length = self.read_u32()? as usize;
+        self.take(length)
+    }
+
+    /// Length followed by UTF-8, copied into the arena.
+    fn read_string(&mut self) -> Result<&'a str, Error> {
+        let pos = self.pos;
+        match std::str::from_utf8(self.read_variable()?) {
+            Ok(s) => Ok(self.arena.alloc_str(s)),
+            Err(e) => Err(llsd_error!(
+                Encoding,
+                "Binary LLSD, invalid UTF-8 in string at position {}: {}",
+                pos,
+                e
+            )),
+        }
+    }
+
+    /// A count of items, each at least `minsize` bytes. Limits preallocation
+    /// to what the remaining input could possibly hold.
+    fn read_count(&mut self, minsize: usize) -> Result<(usize, usize), Error> {
+        let count = self.read_u32()? as usize;
+        Ok((count, count.min((self.b.len() - self.pos) / minsize)))
+    }
+
+    /// Start of a map or array at `pos`. Error if that is too deep.
+    fn enter(&mut self, pos: usize) -> Result<(), Error> {
+        if self.depth >= DEFAULT_MAX_DEPTH {
+            return Err(NestingTooDeep {
+                limit: DEFAULT_MAX_DEPTH,
+                position: pos,
+            }
+            .into());
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Expect the closing byte of a map or array.
+    fn expect_end(&mut self, end: u8) -> Result<(), Error> {
+        let endpos = self.pos;
+        let ch = self.read_u8()?;
+        if ch != end {
+            return Err(anyhow!(
+                "Binary LLSD, expected {:?}, found {:?} at position {}",
+                end as char,
+                ch as char,
+                endpos
+            ));
+        }
+        Ok(())
+    }
+
+    /// Parse one value. Recursive, to a limited depth.
+    fn parse_value(&mut self) -> Result<LLSDValueArena<'a>, Error> {
+        let pos = self.pos; // position of type code
+        let typecode = self.read_u8()?;
+        Ok(match typecode {
+            b'!' => LLSDValueArena::Undefined,
+            b'0' => LLSDValueArena::Boolean(false),
+            b'1' => LLSDValueArena::Boolean(true),
+            b's' => LLSDValueArena::String(self.read_string()?),
+            b'l' => LLSDValueArena::URI(self.read_string()?),
+            b'i' => LLSDValueArena::Integer(self.read_u32()? as i32),
+            b'r' => LLSDValueArena::Real(self.read_f64()?),
+            b'u' => LLSDValueArena::UUID(uuid::Uuid::from_bytes(<[u8; 16]>::try_from(
+                self.take(16)?,
+            )?)),
+            b'b' => LLSDValueArena::Binary(self.arena.alloc_slice_copy(self.read_variable()?)),
+            //  Date is little-endian, unlike Real.
+            b'd' => LLSDValueArena::Date(f64::from_le_bytes(<[u8; 8]>::try_from(self.take(8)?)?)),
+            b'{' => {
+                self.enter(pos)?;
+                let (count, capacity) = self.read_count(6)?; // k, length, and a value
+                let mut entries = BumpVec::with_capacity_in(capacity, self.arena);
+                for _ in 0..count {
+                    let keypos = self.pos;
+                    let keyprefix = self.read_u8()?;
+                    if keyprefix != b'k' {
+                        return Err(anyhow!(
+                            "Binary LLSD map key had {:?} instead of expected 'k' at position {}",
+                            keyprefix as char,
+                            keypos
+                        ));
+                    }
+                    let key = self.read_string()?;
+                    entries.push((key, self.parse_value()?));
+                }
+                self.expect_end(b'}')?;
+                self.depth -= 1;
+                LLSDValueArena::Map(entries.into_bump_slice())
+            }
+            b'[' => {
+                self.enter(pos)?;
+                let (count, capacity) = self.read_count(1)?;
+                let mut items = BumpVec::with_capacity_in(capacity, self.arena);
+                for _ in 0..count {
+                    items.push(self.parse_value()?);
+                }
+                self.expect_end(b']')?;
+                self.depth -= 1;
+                LLSDValueArena::Array(items.into_bump_slice())
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Binary LLSD, unexpected type code {:?} at position {}",
+                    typecode as char,
+                    pos
+                ))
+            }
+        })
+    }
+}
+
+// Unit tests
+
+#[test]
+fn arenaparsetest() {
+    use super::binary;
+    let mut map = HashMap::new();
+    let _ = map.insert("name".to_string(), LLSDValue::String("Vallone".to_string()));
+    let _ = map.insert(
+        "home".to_string(),
+        LLSDValue::URI("http://example.com".to_string()),
+    );
+    let _ = map.insert("id".to_string(), LLSDValue::UUID(uuid::Uuid::nil()));
+    let _ = map.insert("blob".to_string(), LLSDValue::Binary(vec![0, 1, 255]));
+    let _ = map.insert("when".to_string(), LLSDValue::Date(1138804193.5));
+    let _ = map.insert(
+        "list".to_string(),
+        LLSDValue::Array(vec![
+            LLSDValue::Integer(-7),
+            LLSDValue::Real(1.5),
+            LLSDValue::Boolean(true),
+            LLSDValue::Undefined,
+            LLSDValue::Map(HashMap::new()),
+        ]),
+    );
+    let val = LLSDValue::Map(map);
+    let bytes = binary::to_bytes(&val).unwrap();
+    let body = &bytes[binary::LLSDBINARYPREFIX.len()..];
+    let arena = Bump::new();
+    let parsed = parse_in(&arena, body).unwrap();
+    assert_eq!(Some(&LLSDValueArena::String("Vallone")), parsed.get("name"));
+    assert_eq!(val, parsed.to_llsd());
+    //  Duplicate keys, last wins, as in the owned parse.
+    let dups =
+        b"{\x00\x00\x00\x02k\x00\x00\x00\x01ai\x00\x00\x00\x01k\x00\x00\x00\x01ai\x00\x00\x00\x02}";
+    let parsed = parse_in(&arena, dups).unwrap();
+    assert_eq!(Some(&LLSDValueArena::Integer(2)), parsed.get("a"));
+    assert_eq!(binary::parse_array(dups).unwrap(), parsed.to_llsd());
+    //  Errors
+    let err = parse_in(&arena, b"[\x00\x00\x00\x02i\x00\x00\x00\x01]").unwrap_err();
+    println!("Short array: {}", err);
+    assert!(err.to_string().contains("position 10"));
+    assert!(parse_in(&arena, b"[\xff\xff\xff\xff]").is_err()); // huge count
+    assert!(parse_in(
+        &arena,
+        b"{\x00\x00\x00\x01s\x00\x00\x00\x01ai\x00\x00\x00\x01}"
+    )
+    .is_err());
+    //  Bad UTF-8.
+    assert!(parse_in(&arena, b"s\x00\x00\x00\x01\xff").is_err());
+    //  Deep nesting is an error, not a stack overflow.
+    let deep = b"[\x00\x00\x00\x01".repeat(100_000);
+    let err = parse_in(&arena, &deep).unwrap_err();
+    assert!(err.downcast_ref::<NestingTooDeep>().is_some());
+}
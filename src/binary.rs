@@ -10,9 +10,15 @@
 //  March, 2021.
 //  License: LGPL.
 //
-use super::LLSDValue;
+#[cfg(feature = "arena")]
+pub use super::arena::{parse_in, LLSDValueArena};
+use super::{
+    canonical_real_bits, sorted_map_entries, trace_error, Diagnostics, Format, LLSDValue,
+    ParseOptions, DEFAULT_MAX_DEPTH,
+};
 use anyhow::{anyhow, Error};
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::io::{Cursor, Read, Write};
 use uuid;
 //
@@ -22,197 +28,786 @@ pub const LLSDBINARYPREFIX: &[u8] = b"<? LLSD/Binary ?>\n"; // binary LLSD prefi
 pub const LLSDBINARYSENTINEL: &[u8] = LLSDBINARYPREFIX; // prefix must match exactly
 
 ///    Parse LLSD array expressed in binary into an LLSDObject tree. No header.
+///    Error positions are byte offsets into `b`. Trailing whitespace, such as
+///    a newline added by a file tool, is allowed; any other trailing data is an error.
 pub fn parse_array(b: &[u8]) -> Result<LLSDValue, Error> {
+    parse_array_with_options(b, &ParseOptions::default())
+}
+
+///    Parse LLSD array expressed in binary into an LLSDObject tree, with options. No header.
+pub fn parse_array_with_options(b: &[u8], options: &ParseOptions) -> Result<LLSDValue, Error> {
+    trace_error(
+        Format::Binary,
+        parse_slice(b, options, &mut Diagnostics::new(false)),
+    )
+}
+
+///    Parse binary LLSD, no header, collecting diagnostics.
+pub(crate) fn parse_diagnosed(
+    b: &[u8],
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+) -> Result<LLSDValue, Error> {
+    trace_error(Format::Binary, parse_slice(b, options, diags))
+}
+
+///    Parse one value which should be all of `b`, except for trailing whitespace.
+fn parse_slice(
+    b: &[u8],
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+) -> Result<LLSDValue, Error> {
+    options.check_size(b.len())?;
     let mut cursor: Cursor<&[u8]> = Cursor::new(b);
-    parse_value(&mut cursor)
+    let mut reader = PositionReader::new(&mut cursor);
+    let val = parse_value(&mut reader, options, diags)?;
+    let end = reader.pos as usize;
+    if let Some(n) = b[end..].iter().position(|ch| !ch.is_ascii_whitespace()) {
+        return Err(anyhow!(
+            "Binary LLSD, unexpected data after value at position {}",
+            end + n
+        ));
+    }
+    Ok(val)
 }
 
 ///    Parse LLSD reader expressed in binary into an LLSDObject tree. No header.
 pub fn parse_read(cursor: &mut dyn Read) -> Result<LLSDValue, Error> {
-    parse_value(cursor)
+    trace_error(
+        Format::Binary,
+        parse_value(
+            &mut PositionReader::new(cursor),
+            &ParseOptions::default(),
+            &mut Diagnostics::new(false),
+        ),
+    )
+}
+
+///    Parse a stream of binary LLSD messages, each with its own header,
+///    one after another. Yields one value per message. Stops at end of
+///    data between messages, or after the first error.
+pub fn iter_messages<R: Read>(mut r: R) -> impl Iterator<Item = Result<LLSDValue, Error>> {
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        //  End of data is only normal before the first byte of a header.
+        let mut first: [u8; 1] = [0; 1];
+        match r.read(&mut first) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(e) => {
+                done = true;
+                return Some(Err(e.into()));
+            }
+        }
+        let result = read_message(&mut r, first[0]);
+        done = result.is_err();
+        Some(result)
+    })
 }
 
-/// Parse one value - real, integer, map, etc. Recursive.
-fn parse_value(cursor: &mut dyn Read) -> Result<LLSDValue, Error> {
+///    Parse one message, after the first byte of its header.
+fn read_message(r: &mut dyn Read, first: u8) -> Result<LLSDValue, Error> {
+    let mut header = vec![first; LLSDBINARYSENTINEL.len()];
+    r.read_exact(&mut header[1..])?;
+    if header != LLSDBINARYSENTINEL {
+        return Err(anyhow!(
+            "Binary LLSD message header missing or malformed: {:?}",
+            String::from_utf8_lossy(&header)
+        ));
+    }
+    trace_error(
+        Format::Binary,
+        parse_value(
+            &mut PositionReader::new(r),
+            &ParseOptions::default(),
+            &mut Diagnostics::new(false),
+        ),
+    )
+}
+
+///    Read one message framed with a 4-byte big-endian length, as on TCP
+///    transports. The payload is binary LLSD; its header is optional.
+pub fn read_framed<R: Read>(r: &mut R) -> Result<LLSDValue, Error> {
+    let mut lenbytes: [u8; 4] = [0; 4];
+    r.read_exact(&mut lenbytes)?;
+    let len = u32::from_be_bytes(lenbytes) as usize;
+    //  Read through take, so a bad length can't demand huge memory up front.
+    let mut payload = Vec::new();
+    let _ = r.take(len as u64).read_to_end(&mut payload)?;
+    if payload.len() != len {
+        return Err(anyhow!(
+            "Binary LLSD frame length {} exceeds available data, only {} bytes",
+            len,
+            payload.len()
+        ));
+    }
+    parse_array(payload.strip_prefix(LLSDBINARYSENTINEL).unwrap_or(&payload))
+}
+
+///    Parse a binary LLSD array of integers, such as a terrain or parcel grid,
+///    straight into a Vec. No header. Much faster than parse_array for big arrays.
+///    Any element which is not an integer is an error. Trailing data is
+///    treated as by parse_array.
+pub fn parse_integer_array(b: &[u8]) -> Result<Vec<i32>, Error> {
+    const ELEMENTSIZE: usize = 5; // 'i' and 4 bytes
+    if b.len() < 5 || b[0] != b'[' {
+        return Err(anyhow!("Binary LLSD, expected integer array at position 0"));
+    }
+    let count = u32::from_be_bytes([b[1], b[2], b[3], b[4]]) as usize;
+    //  Check length before allocating, so a bad count can't demand huge memory.
+    let endpos = match count
+        .checked_mul(ELEMENTSIZE)
+        .and_then(|n| n.checked_add(5))
+    {
+        Some(endpos) if endpos < b.len() => endpos,
+        _ => {
+            return Err(llsd_error!(
+                Truncated,
+                "Binary LLSD, integer array of {} elements is truncated at position {}",
+                count,
+                b.len()
+            ))
+        }
+    };
+    let mut out = Vec::with_capacity(count);
+    for (n, element) in b[5..endpos].chunks_exact(ELEMENTSIZE).enumerate() {
+        if element[0] != b'i' {
+            return Err(anyhow!(
+                "Binary LLSD, integer array had type code {:?} at position {}",
+                element[0] as char,
+                5 + n * ELEMENTSIZE
+            ));
+        }
+        out.push(i32::from_be_bytes([
+            element[1], element[2], element[3], element[4],
+        ]));
+    }
+    if b[endpos] != b']' {
+        return Err(anyhow!(
+            "Binary LLSD, expected ']' at position {}, found {:?}",
+            endpos,
+            b[endpos] as char
+        ));
+    }
+    if let Some(n) = b[endpos + 1..]
+        .iter()
+        .position(|ch| !ch.is_ascii_whitespace())
+    {
+        return Err(anyhow!(
+            "Binary LLSD, unexpected data after value at position {}",
+            endpos + 1 + n
+        ));
+    }
+    Ok(out)
+}
+
+///    True if `b` is one well-formed binary LLSD value, as `parse_array` would
+///    accept with default options, nesting limit included. Header is optional.
+///    Nothing is built and nothing is allocated except a count for each
+///    open map or array.
+pub fn is_valid(b: &[u8]) -> bool {
+    let b = b.strip_prefix(LLSDBINARYSENTINEL).unwrap_or(b);
+    match validate(b) {
+        Some(end) => b[end..].iter().all(|ch| ch.is_ascii_whitespace()),
+        None => false,
+    }
+}
+
+///    Walk one value without building it. Returns the offset just past it,
+///    or None if malformed.
+fn validate(b: &[u8]) -> Option<usize> {
+    fn take<'a>(b: &'a [u8], pos: &mut usize, n: usize) -> Option<&'a [u8]> {
+        let bytes = b.get(*pos..pos.checked_add(n)?)?;
+        *pos += n;
+        Some(bytes)
+    }
+    fn byte(b: &[u8], pos: &mut usize) -> Option<u8> {
+        Some(take(b, pos, 1)?[0])
+    }
+    fn count(b: &[u8], pos: &mut usize) -> Option<usize> {
+        let mut n: [u8; 4] = [0; 4];
+        n.copy_from_slice(take(b, pos, 4)?);
+        Some(u32::from_be_bytes(n) as usize)
+    }
+    fn string(b: &[u8], pos: &mut usize) -> Option<()> {
+        let len = count(b, pos)?;
+        std::str::from_utf8(take(b, pos, len)?).ok().map(|_| ())
+    }
+
+    let mut pos = 0;
+    let mut stack: Vec<(u8, usize)> = Vec::new(); // terminator and items left, innermost last
+    loop {
+        //  Next item of the innermost open container, or its end.
+        match stack.last_mut() {
+            Some((end, 0)) => {
+                if byte(b, &mut pos)? != *end {
+                    return None;
+                }
+                stack.pop();
+                if stack.is_empty() {
+                    return Some(pos);
+                }
+                continue;
+            }
+            Some((end, left)) => {
+                *left -= 1;
+                if *end == b'}' {
+                    if byte(b, &mut pos)? != b'k' {
+                        return None;
+                    }
+                    string(b, &mut pos)?;
+                }
+            }
+            None => {}
+        }
+        match byte(b, &mut pos)? {
+            b'!' | b'0' | b'1' => {}
+            b's' | b'l' => string(b, &mut pos)?,
+            b'i' => {
+                take(b, &mut pos, 4)?;
+            }
+            b'r' | b'd' => {
+                take(b, &mut pos, 8)?;
+            }
+            b'u' => {
+                take(b, &mut pos, 16)?;
+            }
+            b'b' => {
+                let len = count(b, &mut pos)?;
+                take(b, &mut pos, len)?;
+            }
+            b'{' | b'[' if stack.len() >= DEFAULT_MAX_DEPTH => return None, // too deep to parse
+            b'{' => stack.push((b'}', count(b, &mut pos)?)),
+            b'[' => stack.push((b']', count(b, &mut pos)?)),
+            _ => return None,
+        }
+        if stack.is_empty() {
+            return Some(pos); // a scalar at top level
+        }
+    }
+}
+
+///    Report the LLSD type name of the top-level value, such as "map" or "array",
+///    without parsing the rest. Header is optional.
+pub fn peek_type(b: &[u8]) -> Result<&'static str, Error> {
+    let b = if b.starts_with(LLSDBINARYSENTINEL) {
+        &b[LLSDBINARYSENTINEL.len()..]
+    } else {
+        b
+    };
+    match b.first() {
+        Some(b'!') => Ok("undef"),
+        Some(b'0') | Some(b'1') => Ok("boolean"),
+        Some(b's') => Ok("string"),
+        Some(b'l') => Ok("uri"),
+        Some(b'i') => Ok("integer"),
+        Some(b'r') => Ok("real"),
+        Some(b'u') => Ok("uuid"),
+        Some(b'b') => Ok("binary"),
+        Some(b'd') => Ok("date"),
+        Some(b'{') => Ok("map"),
+        Some(b'[') => Ok("array"),
+        Some(typecode) => Err(anyhow!("Binary LLSD, unexpected type code {:?}", typecode)),
+        None => Err(anyhow!("Binary LLSD, no data")),
+    }
+}
+
+/// Reader which keeps track of how far into the input it is, for error messages.
+struct PositionReader<'a> {
+    inner: &'a mut dyn Read,
+    pos: u64, // bytes read so far
+}
+
+impl<'a> PositionReader<'a> {
+    fn new(inner: &'a mut dyn Read) -> PositionReader<'a> {
+        PositionReader { inner, pos: 0 }
+    }
+
+    /// Fill the buffer. Running out of data is an error, with the position.
+    fn read_all(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        let pos = self.pos;
+        match self.read_exact(buf) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Err(llsd_error!(
+                Truncated,
+                "Binary LLSD, unexpected end of data reading {} bytes at position {}",
+                buf.len(),
+                pos
+            )),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl<'a> Read for PositionReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// Parse one value - real, integer, map, etc. Not recursive, so deeply
+/// nested input cannot overflow the stack.
+/// Positions in error messages are byte offsets from the start of the value.
+fn parse_value(
+    cursor: &mut PositionReader,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+) -> Result<LLSDValue, Error> {
     //  These could be generic if generics with numeric parameters were in stable Rust.
-    fn read_u8(cursor: &mut dyn Read) -> Result<u8, Error> {
+    fn read_u8(cursor: &mut PositionReader) -> Result<u8, Error> {
         let mut b: [u8; 1] = [0; 1];
-        cursor.read_exact(&mut b)?; // read one byte
+        cursor.read_all(&mut b)?; // read one byte
         Ok(b[0])
     }
-    fn read_u32(cursor: &mut dyn Read) -> Result<u32, Error> {
+    fn read_u32(cursor: &mut PositionReader) -> Result<u32, Error> {
         let mut b: [u8; 4] = [0; 4];
-        cursor.read_exact(&mut b)?; // read one byte
+        cursor.read_all(&mut b)?;
         Ok(u32::from_be_bytes(b))
     }
-    fn read_i32(cursor: &mut dyn Read) -> Result<i32, Error> {
+    fn read_i32(cursor: &mut PositionReader) -> Result<i32, Error> {
         let mut b: [u8; 4] = [0; 4];
-        cursor.read_exact(&mut b)?; // read one byte
+        cursor.read_all(&mut b)?;
         Ok(i32::from_be_bytes(b))
     }
-    fn read_i64(cursor: &mut dyn Read) -> Result<i64, Error> {
-        let mut b: [u8; 8] = [0; 8];
-        cursor.read_exact(&mut b)?; // read one byte
-        Ok(i64::from_be_bytes(b))
-    }
-    fn read_f64(cursor: &mut dyn Read) -> Result<f64, Error> {
+    fn read_f64(cursor: &mut PositionReader) -> Result<f64, Error> {
         let mut b: [u8; 8] = [0; 8];
-        cursor.read_exact(&mut b)?; // read one byte
+        cursor.read_all(&mut b)?;
         Ok(f64::from_be_bytes(b))
     }
-    fn read_variable(cursor: &mut dyn Read) -> Result<Vec<u8>, Error> {
+    fn read_variable(cursor: &mut PositionReader) -> Result<Vec<u8>, Error> {
         let length = read_u32(cursor)?; // read length in bytes
-        let mut buf = vec![0u8; length as usize];
-        cursor.read(&mut buf)?;
+        read_bytes(cursor, length)
+    }
+    /// The length comes from the input, so the buffer grows as the bytes
+    /// arrive, rather than trusting the length for one big allocation.
+    fn read_bytes(cursor: &mut PositionReader, length: u32) -> Result<Vec<u8>, Error> {
+        const CHUNK: u32 = 65536; // allocated up front, at most
+        let pos = cursor.pos;
+        let mut buf = Vec::with_capacity(length.min(CHUNK) as usize);
+        (&mut *cursor)
+            .take(u64::from(length))
+            .read_to_end(&mut buf)?;
+        if buf.len() < length as usize {
+            return Err(llsd_error!(
+                Truncated,
+                "Binary LLSD, unexpected end of data reading {} bytes at position {}",
+                length,
+                pos
+            ));
+        }
         Ok(buf) // read bytes of string
     }
+    fn read_string(cursor: &mut PositionReader, options: &ParseOptions) -> Result<String, Error> {
+        let pos = cursor.pos;
+        let bytes = read_variable(cursor)?;
+        check_string(bytes, pos, options)
+    }
+    fn check_string(bytes: Vec<u8>, pos: u64, options: &ParseOptions) -> Result<String, Error> {
+        let s = match String::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(llsd_error!(
+                    Encoding,
+                    "Binary LLSD, invalid UTF-8 in string at position {}: {}",
+                    pos,
+                    e
+                ))
+            }
+        };
+        if options.reject_nul_in_strings && s.contains('\0') {
+            return Err(llsd_error!(
+                Value,
+                "Binary LLSD, NUL character in string {:?} at position {}",
+                s,
+                pos
+            ));
+        }
+        Ok(s)
+    }
+
+    /// Map key, 'k' then length and bytes. Lenient mode also takes keys without
+    /// the 'k'. The byte read is then the high byte of the key length, which is
+    /// never 'k' for a real key. A zero-length key is valid, and gives "".
+    fn read_key(cursor: &mut PositionReader, options: &ParseOptions) -> Result<String, Error> {
+        let keypos = cursor.pos;
+        match read_u8(cursor)? {
+            b'k' => read_string(cursor, options),
+            first if options.lenient => {
+                let mut b: [u8; 4] = [first, 0, 0, 0];
+                cursor.read_all(&mut b[1..])?;
+                let bytes = read_bytes(cursor, u32::from_be_bytes(b))?;
+                check_string(bytes, keypos, options)
+            }
+            keyprefix => Err(anyhow!(
+                "Binary LLSD map key had {:?} instead of expected 'k' at position {}",
+                keyprefix as char,
+                keypos
+            )),
+        }
+    }
+
+    /// A map or array being parsed. Open containers are kept on an explicit
+    /// stack instead of recursing, so any depth parses in constant stack space.
+    enum Open {
+        Map {
+            dict: HashMap<String, LLSDValue>,
+            remaining: u32, // items not yet read
+            key: String,    // key of the item being read
+        },
+        Array {
+            items: Vec<LLSDValue>,
+            remaining: u32,
+        },
+    }
+
+    /// Start of a value: either all of a scalar, or an open container.
+    enum Started {
+        Value(LLSDValue),
+        Open(Open),
+    }
 
-    let typecode = read_u8(cursor)?;
-    match typecode {
-        //  Undefined - the empty value
-        b'!' => Ok(LLSDValue::Undefined),
-        //  Boolean - 1 or 0
-        b'0' => Ok(LLSDValue::Boolean(false)),
-        b'1' => Ok(LLSDValue::Boolean(true)),
-        //  String - length followed by data
-        b's' => Ok(LLSDValue::String(
-            std::str::from_utf8(&read_variable(cursor)?)?.to_string(),
-        )),
-        //  URI - length followed by data
-        b'l' => Ok(LLSDValue::URI(
-            std::str::from_utf8(&read_variable(cursor)?)?.to_string(),
-        )),
-        //  Integer - 4 bytes
-        b'i' => Ok(LLSDValue::Integer(read_i32(cursor)?)),
-        //  Real - 4 bytes
-        b'r' => Ok(LLSDValue::Real(read_f64(cursor)?)),
-        //  UUID - 16 bytes
-        b'u' => {
-            let mut buf: [u8; 16] = [0u8; 16];
-            cursor.read(&mut buf)?; // read bytes of string
-            Ok(LLSDValue::UUID(uuid::Uuid::from_bytes(buf)))
-        }
-        //  Binary - length followed by data
-        b'b' => Ok(LLSDValue::Binary(read_variable(cursor)?)),
-        //  Date - 64 bits
-        b'd' => Ok(LLSDValue::Date(read_i64(cursor)?)),
-        //  Map -- keyed collection of items
-        b'{' => {
-            let mut dict: HashMap<String, LLSDValue> = HashMap::new(); // accumulate hash here
-            let count = read_u32(cursor)?; // number of items
-            for _ in 0..count {
-                let keyprefix = &read_u8(cursor)?; // key should begin with b'k';
-                match keyprefix {
-                    b'k' => {
-                        let key = std::str::from_utf8(&read_variable(cursor)?)?.to_string();
-                        let _ = dict.insert(key, parse_value(cursor)?); // recurse and add, allowing dups
+    /// Read a scalar, or the type code and count of a container.
+    fn start_value(cursor: &mut PositionReader, options: &ParseOptions) -> Result<Started, Error> {
+        let pos = cursor.pos; // position of type code
+        let typecode = read_u8(cursor)?;
+        llsd_trace!(position = pos, typecode = ?(typecode as char), "binary LLSD value");
+        Ok(Started::Value(match typecode {
+            //  Undefined - the empty value
+            b'!' => LLSDValue::Undefined,
+            //  Boolean - 1 or 0
+            b'0' => LLSDValue::Boolean(false),
+            b'1' => LLSDValue::Boolean(true),
+            //  String - length followed by data
+            b's' => LLSDValue::String(read_string(cursor, options)?),
+            //  URI - length followed by data
+            b'l' => LLSDValue::URI(read_string(cursor, options)?),
+            //  Integer - 4 bytes
+            b'i' => LLSDValue::Integer(read_i32(cursor)?),
+            //  Real - 4 bytes
+            b'r' => LLSDValue::Real(read_f64(cursor)?),
+            //  UUID - 16 bytes
+            b'u' => {
+                let mut buf: [u8; 16] = [0u8; 16];
+                cursor.read_all(&mut buf)?; // read bytes of UUID
+                LLSDValue::UUID(uuid::Uuid::from_bytes(buf))
+            }
+            //  Binary - length followed by data
+            b'b' => LLSDValue::Binary(read_variable(cursor)?),
+            //  Date - 8 byte real, seconds since epoch. Little-endian, unlike Real.
+            b'd' => {
+                let mut b: [u8; 8] = [0; 8];
+                cursor.read_all(&mut b)?;
+                LLSDValue::Date(f64::from_le_bytes(b))
+            }
+            //  Map -- keyed collection of items
+            b'{' => {
+                let count = read_u32(cursor)?; // number of items
+                llsd_trace!(count, "binary LLSD map");
+                return Ok(Started::Open(Open::Map {
+                    dict: HashMap::new(), // accumulate hash here
+                    remaining: count,
+                    key: String::new(),
+                }));
+            }
+            //  Array -- array of items
+            b'[' => {
+                let count = read_u32(cursor)?; // number of items
+                return Ok(Started::Open(Open::Array {
+                    items: Vec::new(), // accumulate items here
+                    remaining: count,
+                }));
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Binary LLSD, unexpected type code {:?} at position {}",
+                    typecode as char,
+                    pos
+                ))
+            }
+        }))
+    }
+
+    /// Parse, keeping open containers on `stack`, innermost last.
+    fn parse_items(
+        cursor: &mut PositionReader,
+        options: &ParseOptions,
+        diags: &mut Diagnostics,
+        stack: &mut Vec<Open>,
+    ) -> Result<LLSDValue, Error> {
+        loop {
+            //  Next item of the innermost open container, or its end.
+            let value = match stack.last_mut() {
+                Some(Open::Map { remaining: 0, .. }) | Some(Open::Array { remaining: 0, .. }) => {
+                    let endpos = cursor.pos;
+                    let end = read_u8(cursor)?;
+                    match stack.pop() {
+                        Some(Open::Map { dict, .. }) => {
+                            if end != b'}' {
+                                return Err(anyhow!(
+                                    "Binary LLSD map did not end properly with }} at position {}",
+                                    endpos
+                                ));
+                            }
+                            LLSDValue::Map(dict)
+                        }
+                        Some(Open::Array { items, .. }) => {
+                            if end != b']' {
+                                return Err(anyhow!(
+                                    "Binary LLSD array did not end properly with ] at position {}",
+                                    endpos
+                                ));
+                            }
+                            LLSDValue::Array(items)
+                        }
+                        None => unreachable!(),
+                    }
+                }
+                top => {
+                    match top {
+                        Some(Open::Map { key, .. }) => {
+                            *key = read_key(cursor, options)?;
+                            diags.push_key(key);
+                        }
+                        Some(Open::Array { items, .. }) => diags.push_index(items.len()),
+                        None => {}
                     }
-                    _ => {
-                        return Err(anyhow!(
-                            "Binary LLSD map key had {:?} instead of expected 'k'",
-                            keyprefix
-                        ))
+                    let pos = cursor.pos;
+                    match start_value(cursor, options)? {
+                        Started::Value(value) => value,
+                        Started::Open(open) => {
+                            options.check_depth(stack.len() + 1, pos as usize)?;
+                            stack.push(open);
+                            continue;
+                        }
                     }
                 }
+            };
+            //  Add the finished value to its container, or return it if outermost.
+            match stack.last_mut() {
+                None => return Ok(value),
+                Some(Open::Map {
+                    dict,
+                    remaining,
+                    key,
+                }) => {
+                    diags.pop();
+                    diags.insert(dict, std::mem::take(key), value); // add, allowing dups
+                    *remaining -= 1;
+                }
+                Some(Open::Array { items, remaining }) => {
+                    diags.pop();
+                    items.push(value);
+                    *remaining -= 1;
+                }
             }
-            if read_u8(cursor)? != b'}' {
-                return Err(anyhow!("Binary LLSD map did not end properly with }}"));
-            }
-            Ok(LLSDValue::Map(dict))
         }
-        //  Array -- array of items
-        b'[' => {
-            let mut array: Vec<LLSDValue> = Vec::new(); // accumulate hash here
-            let count = read_u32(cursor)?; // number of items
-            for _ in 0..count {
-                array.push(parse_value(cursor)?); // recurse and add, allowing dups
-            }
-            if read_u8(cursor)? != b']' {
-                return Err(anyhow!("Binary LLSD array did not end properly with ] "));
-            }
-            Ok(LLSDValue::Array(array))
+    }
+
+    let mut stack: Vec<Open> = Vec::new();
+    let result = parse_items(cursor, options, diags, &mut stack);
+    //  After an error, the open containers may hold deep trees. Dropping
+    //  those the normal way would recurse.
+    let mut work: Vec<LLSDValue> = Vec::new();
+    for open in stack {
+        match open {
+            Open::Map { dict, .. } => work.extend(dict.into_values()),
+            Open::Array { items, .. } => work.extend(items),
+        }
+    }
+    while let Some(val) = work.pop() {
+        match val {
+            LLSDValue::Map(m) => work.extend(m.into_values()),
+            LLSDValue::Array(a) => work.extend(a),
+            _ => {}
         }
+    }
+    result
+}
 
-        _ => Err(anyhow!("Binary LLSD, unexpected type code {:?}", typecode)),
+/// Options for binary output. The default follows the LLSD spec.
+#[derive(Debug, Clone)]
+pub struct BinaryOptions {
+    /// Write 'k' before each map key, as the spec requires. Some other
+    /// implementations have been seen without it; turn this off to talk to them.
+    pub emit_key_prefix: bool,
+}
+
+impl Default for BinaryOptions {
+    fn default() -> Self {
+        BinaryOptions {
+            emit_key_prefix: true,
+        }
     }
 }
 
 /// Outputs an LLSDValue as a string of bytes, in LLSD "binary" format.
 pub fn to_bytes(val: &LLSDValue) -> Result<Vec<u8>, Error> {
+    to_bytes_with_options(val, &BinaryOptions::default())
+}
+
+/// Outputs an LLSDValue as a string of bytes, in LLSD "binary" format, with options.
+pub fn to_bytes_with_options(val: &LLSDValue, options: &BinaryOptions) -> Result<Vec<u8>, Error> {
     let mut s: Vec<u8> = Vec::new();
-    s.write(LLSDBINARYPREFIX)?; // prefix
-    generate_value(&mut s, val)?;
+    s.write_all(LLSDBINARYPREFIX)?; // prefix
+    generate_value(&mut s, val, false, options)?;
+    s.flush()?;
+    Ok(s)
+}
+
+/// Writes one message framed with a 4-byte big-endian length, then the
+/// binary LLSD, with header, as from `to_bytes`.
+pub fn write_framed<W: Write>(w: &mut W, val: &LLSDValue) -> Result<(), Error> {
+    let payload = to_bytes(val)?;
+    let mut frame = Vec::with_capacity(payload.len() + 4);
+    write_length(&mut frame, payload.len())?;
+    frame.extend_from_slice(&payload);
+    w.write_all(&frame)?;
+    Ok(())
+}
+
+/// Outputs an array of integers in LLSD "binary" format. Same bytes as
+/// to_bytes of an Array of Integers, without building the LLSDValue.
+pub fn to_bytes_integer_array(v: &[i32]) -> Result<Vec<u8>, Error> {
+    let mut s: Vec<u8> = Vec::new();
+    s.write_all(LLSDBINARYPREFIX)?; // prefix
+    write_integer_array(&mut s, v.iter().copied())?;
+    Ok(s)
+}
+
+/// Outputs an LLSDValue in LLSD "binary" format, in canonical form.
+/// Values which are equal produce identical bytes, so the result can be
+/// signed or hashed. Map keys are in sorted order, all NaN reals and dates are
+/// written with the same bit pattern, and -0.0 is written as 0.0.
+pub fn to_bytes_canonical(val: &LLSDValue) -> Result<Vec<u8>, Error> {
+    let mut s: Vec<u8> = Vec::new();
+    s.write_all(LLSDBINARYPREFIX)?; // prefix
+    generate_value(&mut s, val, true, &BinaryOptions::default())?;
     s.flush()?;
     Ok(s)
 }
 
 /// Generate one <TYPE> VALUE </TYPE> output. VALUE is recursive.
-fn generate_value(s: &mut Vec<u8>, val: &LLSDValue) -> Result<(), Error> {
+fn generate_value(
+    s: &mut Vec<u8>,
+    val: &LLSDValue,
+    canonical: bool,
+    options: &BinaryOptions,
+) -> Result<(), Error> {
     //  Emit binary for all possible types.
     match val {
-        LLSDValue::Undefined => s.write(b"!")?,
-        LLSDValue::Boolean(v) => s.write(if *v { b"1" } else { b"0" })?,
+        LLSDValue::Undefined => s.write_all(b"!")?,
+        LLSDValue::Boolean(v) => s.write_all(if *v { b"1" } else { b"0" })?,
         LLSDValue::String(v) => {
-            s.write(b"s")?;
-            s.write(&(v.len() as u32).to_be_bytes())?;
-            s.write(&v.as_bytes())?
+            s.write_all(b"s")?;
+            write_length(s, v.len())?;
+            s.write_all(v.as_bytes())?
         }
         LLSDValue::URI(v) => {
-            s.write(b"l")?;
-            s.write(&(v.len() as u32).to_be_bytes())?;
-            s.write(v.as_bytes())?
+            s.write_all(b"l")?;
+            write_length(s, v.len())?;
+            s.write_all(v.as_bytes())?
         }
         LLSDValue::Integer(v) => {
-            s.write(b"i")?;
-            s.write(&v.to_be_bytes())?
+            s.write_all(b"i")?;
+            s.write_all(&v.to_be_bytes())?
         }
         LLSDValue::Real(v) => {
-            s.write(b"r")?;
-            s.write(&v.to_be_bytes())?
+            s.write_all(b"r")?;
+            if canonical {
+                s.write_all(&canonical_real_bits(*v).to_be_bytes())?
+            } else {
+                s.write_all(&v.to_be_bytes())?
+            }
         }
         LLSDValue::UUID(v) => {
-            s.write(b"u")?;
-            s.write(v.as_bytes())?
+            s.write_all(b"u")?;
+            s.write_all(v.as_bytes())?
         }
         LLSDValue::Binary(v) => {
-            s.write(b"b")?;
-            s.write(&(v.len() as u32).to_be_bytes())?;
-            s.write(v)?
+            s.write_all(b"b")?;
+            write_length(s, v.len())?;
+            s.write_all(v)?
         }
         LLSDValue::Date(v) => {
-            s.write(b"d")?;
-            s.write(&v.to_be_bytes())?
+            s.write_all(b"d")?;
+            if canonical {
+                s.write_all(&canonical_real_bits(*v).to_le_bytes())?
+            } else {
+                s.write_all(&v.to_le_bytes())?
+            }
         }
 
         //  Map is { childcnt key value key value ... }
         LLSDValue::Map(v) => {
             //  Output count of key/value pairs
-            s.write(b"{")?;
-            s.write(&(v.len() as u32).to_be_bytes())?;
+            s.write_all(b"{")?;
+            write_length(s, v.len())?;
             //  Output key/value pairs
-            for (key, value) in v {
-                s.write(&[b'k'])?; // k prefix to key. UNDOCUMENTED
-                s.write(&(key.len() as u32).to_be_bytes())?;
-                s.write(&key.as_bytes())?;
-                generate_value(s, value)?;
+            for (key, value) in sorted_map_entries(v) {
+                if options.emit_key_prefix {
+                    s.write_all(b"k")?; // k prefix to key
+                }
+                write_length(s, key.len())?;
+                s.write_all(key.as_bytes())?;
+                generate_value(s, value, canonical, options)?;
             }
-            s.write(b"}")?
+            s.write_all(b"}")?
         }
         //  Array is [ childcnt child child ... ]
+        LLSDValue::Array(v) if v.iter().all(|value| matches!(value, LLSDValue::Integer(_))) => {
+            //  Common for grids, and worth a fast path. All integers, checked above.
+            write_integer_array(s, v.iter().map(|value| *value.as_integer().unwrap_or(&0)))?
+        }
         LLSDValue::Array(v) => {
             //  Output count of array entries
-            s.write(b"[")?;
-            s.write(&(v.len() as u32).to_be_bytes())?;
+            s.write_all(b"[")?;
+            write_length(s, v.len())?;
             //  Output array entries
             for value in v {
-                generate_value(s, value)?;
+                generate_value(s, value, canonical, options)?;
             }
-            s.write(b"]")?
+            s.write_all(b"]")?
         }
     };
     Ok(())
 }
 
+/// Write an array of integers, [ count i N i N ... ], in one pass.
+fn write_integer_array(
+    s: &mut Vec<u8>,
+    v: impl ExactSizeIterator<Item = i32>,
+) -> Result<(), Error> {
+    s.reserve(v.len() * 5 + 6);
+    s.push(b'[');
+    write_length(s, v.len())?;
+    for n in v {
+        s.push(b'i');
+        s.extend_from_slice(&n.to_be_bytes());
+    }
+    s.push(b']');
+    Ok(())
+}
+
+/// Write a length or count as 4 bytes. Lengths over 4GB cannot be represented.
+fn write_length(s: &mut Vec<u8>, len: usize) -> Result<(), Error> {
+    match u32::try_from(len) {
+        Ok(len) => s.write_all(&len.to_be_bytes())?,
+        Err(_) => {
+            return Err(anyhow!(
+                "Binary LLSD, value too large: length {} exceeds {}",
+                len,
+                u32::MAX
+            ))
+        }
+    }
+    Ok(())
+}
+
 // Unit test
 
 #[test]
@@ -239,3 +834,403 @@ fn binaryparsetest1() {
     //  Check that results match after round trip.
     assert_eq!(test1, test1value);
 }
+
+#[test]
+fn binarypeektypetest() {
+    let map = LLSDValue::Map(HashMap::new());
+    let array = LLSDValue::Array(vec![LLSDValue::Integer(1)]);
+    assert_eq!("map", peek_type(&to_bytes(&map).unwrap()).unwrap());
+    assert_eq!("array", peek_type(&to_bytes(&array).unwrap()).unwrap());
+    assert_eq!(
+        "integer",
+        peek_type(&to_bytes(&LLSDValue::Integer(5)).unwrap()).unwrap()
+    );
+    //  Works without the header, too.
+    assert_eq!(
+        "array",
+        peek_type(&to_bytes(&array).unwrap()[LLSDBINARYSENTINEL.len()..]).unwrap()
+    );
+    assert!(peek_type(b"").is_err());
+    assert!(peek_type(b"x").is_err());
+}
+
+#[test]
+fn binarylengthtest() {
+    //  Don't actually build a 4GB string; check the length writer directly.
+    let mut s: Vec<u8> = Vec::new();
+    write_length(&mut s, u32::MAX as usize).unwrap();
+    assert_eq!(vec![0xff, 0xff, 0xff, 0xff], s);
+    let err = write_length(&mut s, u32::MAX as usize + 1).unwrap_err();
+    println!("Error as expected: {:?}", err);
+    assert!(err.to_string().contains("too large"));
+}
+
+#[test]
+fn binaryitermessagestest() {
+    let values = vec![
+        LLSDValue::Integer(1),
+        LLSDValue::Array(vec![LLSDValue::String("two".to_string())]),
+        LLSDValue::Map(HashMap::new()),
+    ];
+    let mut stream: Vec<u8> = Vec::new();
+    for val in &values {
+        stream.extend_from_slice(&to_bytes(val).unwrap());
+    }
+    let parsed: Vec<LLSDValue> = iter_messages(Cursor::new(&stream))
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(values, parsed);
+    //  Missing header on the second message
+    let mut bad = to_bytes(&values[0]).unwrap();
+    bad.extend_from_slice(b"i\x00\x00\x00\x02");
+    let results: Vec<Result<LLSDValue, Error>> = iter_messages(Cursor::new(&bad)).collect();
+    assert_eq!(2, results.len());
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    //  Truncated message
+    let truncated = &stream[..stream.len() - 1];
+    assert!(iter_messages(Cursor::new(truncated)).any(|r| r.is_err()));
+}
+
+#[test]
+fn binarynulstringtest() {
+    let test1 = LLSDValue::Array(vec![LLSDValue::String("ab\0cd".to_string())]);
+    let generated = to_bytes(&test1).unwrap();
+    let body = &generated[LLSDBINARYSENTINEL.len()..];
+    //  Accepted by default.
+    assert_eq!(test1, parse_array(body).unwrap());
+    //  Rejected on request.
+    let options = ParseOptions {
+        reject_nul_in_strings: true,
+        ..Default::default()
+    };
+    let err = parse_array_with_options(body, &options).unwrap_err();
+    println!("Error as expected: {:?}", err);
+    //  Also applies to map keys.
+    let test2 = LLSDValue::Map(
+        [("k\0".to_string(), LLSDValue::Undefined)]
+            .iter()
+            .cloned()
+            .collect(),
+    );
+    let generated = to_bytes(&test2).unwrap();
+    assert!(parse_array_with_options(&generated[LLSDBINARYSENTINEL.len()..], &options).is_err());
+}
+
+#[test]
+fn binaryerrorpositiontest() {
+    //  Array of two, second item has a bad type code at offset 10.
+    let bad: &[u8] = b"[\x00\x00\x00\x02i\x00\x00\x00\x01?]";
+    let err = parse_array(bad).unwrap_err().to_string();
+    println!("Error as expected: {}", err);
+    assert!(err.contains("'?' at position 10"));
+    //  Truncated integer. Reading 4 bytes starting at 6.
+    let err = parse_array(&bad[..8]).unwrap_err().to_string();
+    println!("Error as expected: {}", err);
+    assert!(err.contains("at position 6"));
+    //  Missing array end.
+    let err = parse_array(b"[\x00\x00\x00\x00}").unwrap_err().to_string();
+    assert!(err.contains("at position 5"));
+}
+
+#[test]
+fn binarykeyprefixtest() {
+    let test1 = LLSDValue::Map(
+        [
+            ("a".to_string(), LLSDValue::Integer(1)),
+            ("bc".to_string(), LLSDValue::String("x".to_string())),
+        ]
+        .iter()
+        .cloned()
+        .collect(),
+    );
+    let lenient = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    let with_prefix = to_bytes(&test1).unwrap();
+    let without_prefix = to_bytes_with_options(
+        &test1,
+        &BinaryOptions {
+            emit_key_prefix: false,
+        },
+    )
+    .unwrap();
+    assert_eq!(with_prefix.len(), without_prefix.len() + 2);
+    assert!(without_prefix.ends_with(b"\x00\x00\x00\x02bcs\x00\x00\x00\x01x}"));
+    //  Standard framing parses either way.
+    assert_eq!(
+        test1,
+        parse_array(&with_prefix[LLSDBINARYSENTINEL.len()..]).unwrap()
+    );
+    assert_eq!(
+        test1,
+        parse_array_with_options(&with_prefix[LLSDBINARYSENTINEL.len()..], &lenient).unwrap()
+    );
+    //  Without the prefix needs lenient mode.
+    assert!(parse_array(&without_prefix[LLSDBINARYSENTINEL.len()..]).is_err());
+    assert_eq!(
+        test1,
+        parse_array_with_options(&without_prefix[LLSDBINARYSENTINEL.len()..], &lenient).unwrap()
+    );
+}
+
+#[test]
+fn binaryintegerarraytest() {
+    let ints: Vec<i32> = vec![0, 1, -1, i32::MAX, i32::MIN, 12345];
+    let val = LLSDValue::Array(ints.iter().map(|&n| LLSDValue::Integer(n)).collect());
+    //  Fast path output is standard binary LLSD.
+    let generated = to_bytes(&val).unwrap();
+    assert_eq!(generated, to_bytes_integer_array(&ints).unwrap());
+    let body = &generated[LLSDBINARYPREFIX.len()..];
+    assert_eq!(val, parse_array(body).unwrap());
+    assert_eq!(ints, parse_integer_array(body).unwrap());
+    assert_eq!(
+        Vec::<i32>::new(),
+        parse_integer_array(b"[\x00\x00\x00\x00]").unwrap()
+    );
+    //  Mixed arrays take the general path.
+    let mixed = LLSDValue::Array(vec![LLSDValue::Integer(1), LLSDValue::Real(2.0)]);
+    let generated = to_bytes(&mixed).unwrap();
+    assert_eq!(
+        mixed,
+        parse_array(&generated[LLSDBINARYPREFIX.len()..]).unwrap()
+    );
+    assert!(parse_integer_array(&generated[LLSDBINARYPREFIX.len()..]).is_err());
+    //  Errors
+    assert!(parse_integer_array(b"[\x00\x00\x00\x02i\x00\x00\x00\x01]").is_err()); // short
+    assert!(parse_integer_array(b"[\xff\xff\xff\xff]").is_err()); // huge count
+    assert!(parse_integer_array(b"[\x00\x00\x00\x01i\x00\x00\x00\x01}").is_err());
+    assert!(parse_integer_array(b"{\x00\x00\x00\x00}").is_err());
+    //  Trailing data, as for parse_array. Whitespace is allowed.
+    let err = parse_integer_array(b"[\x00\x00\x00\x00]i").unwrap_err();
+    assert!(err.to_string().contains("position 6"));
+    assert!(parse_array(b"[\x00\x00\x00\x00]i").is_err());
+    assert!(parse_integer_array(b"[\x00\x00\x00\x00]\n").is_ok());
+}
+
+#[test]
+fn binaryframedtest() {
+    let first = LLSDValue::Array(vec![
+        LLSDValue::Integer(1),
+        LLSDValue::String("two".to_string()),
+    ]);
+    let second = LLSDValue::Real(3.5);
+    let mut stream = Cursor::new(Vec::new());
+    write_framed(&mut stream, &first).unwrap();
+    write_framed(&mut stream, &second).unwrap();
+    let bytes = stream.into_inner();
+    assert_eq!(&bytes[4..4 + LLSDBINARYPREFIX.len()], LLSDBINARYPREFIX);
+    let mut stream = Cursor::new(bytes);
+    assert_eq!(first, read_framed(&mut stream).unwrap());
+    assert_eq!(second, read_framed(&mut stream).unwrap());
+    //  No more frames.
+    assert!(read_framed(&mut stream).is_err());
+    //  Header is optional.
+    assert_eq!(
+        LLSDValue::Integer(7),
+        read_framed(&mut Cursor::new(b"\x00\x00\x00\x05i\x00\x00\x00\x07")).unwrap()
+    );
+    //  Frame longer than the data
+    let err = read_framed(&mut Cursor::new(b"\x00\x00\x01\x00i\x00\x00\x00\x07")).unwrap_err();
+    println!("Short frame: {}", err);
+    assert!(err.to_string().contains("exceeds available data"));
+    assert!(read_framed(&mut Cursor::new(b"\xff\xff\xff\xff!")).is_err());
+}
+
+#[test]
+fn binarytrailingdatatest() {
+    let val = LLSDValue::Array(vec![
+        LLSDValue::Integer(1),
+        LLSDValue::String("a".to_string()),
+    ]);
+    let mut bytes = to_bytes(&val).unwrap();
+    bytes.extend_from_slice(b"\n");
+    assert_eq!(val, LLSDValue::parse(&bytes).unwrap());
+    bytes.extend_from_slice(b" \r\n\t");
+    assert_eq!(val, parse_array(&bytes[LLSDBINARYPREFIX.len()..]).unwrap());
+    //  Anything else after the value is an error.
+    let good = bytes.len();
+    bytes.extend_from_slice(b"i");
+    let err = LLSDValue::parse(&bytes).unwrap_err();
+    println!("Trailing data: {}", err);
+    assert!(err
+        .to_string()
+        .contains(&format!("position {}", good - LLSDBINARYPREFIX.len())));
+    assert!(parse_array(b"i\x00\x00\x00\x01\x00").is_err());
+}
+
+#[test]
+fn binaryemptycontainertest() {
+    //  A zero count is followed directly by the terminator.
+    let cases: [(LLSDValue, &[u8]); 2] = [
+        (LLSDValue::Map(HashMap::new()), b"{\x00\x00\x00\x00}"),
+        (LLSDValue::Array(Vec::new()), b"[\x00\x00\x00\x00]"),
+    ];
+    for (val, body) in cases.iter() {
+        let generated = to_bytes(val).unwrap();
+        assert_eq!(*body, &generated[LLSDBINARYPREFIX.len()..]);
+        assert_eq!(*val, parse_array(body).unwrap());
+        assert_eq!(*val, parse_read(&mut Cursor::new(*body)).unwrap());
+        //  Missing or wrong terminator.
+        assert!(parse_array(&body[..5]).is_err());
+        let mut wrong = body.to_vec();
+        wrong[5] = if wrong[5] == b'}' { b']' } else { b'}' };
+        let err = parse_array(&wrong).unwrap_err().to_string();
+        println!("Error as expected: {}", err);
+        assert!(err.contains("position 5"));
+    }
+    //  Empty containers nested, in both orders.
+    let nested = crate::notation::parse(b"{'a':[],'b':{},'c':[{},[]]}").unwrap();
+    let generated = to_bytes(&nested).unwrap();
+    assert_eq!(
+        nested,
+        parse_array(&generated[LLSDBINARYPREFIX.len()..]).unwrap()
+    );
+    assert!(parse_integer_array(b"[\x00\x00\x00\x00]")
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn binarynestedundefinedtest() {
+    let array = LLSDValue::Array(vec![LLSDValue::Undefined, LLSDValue::Integer(1)]);
+    let generated = to_bytes(&array).unwrap();
+    assert_eq!(
+        b"[\x00\x00\x00\x02!i\x00\x00\x00\x01]",
+        &generated[LLSDBINARYPREFIX.len()..]
+    );
+    assert_eq!(array, LLSDValue::parse(&generated).unwrap());
+    let map = LLSDValue::Map(
+        [("x".to_string(), LLSDValue::Undefined)]
+            .iter()
+            .cloned()
+            .collect(),
+    );
+    let generated = to_bytes(&map).unwrap();
+    assert_eq!(
+        b"{\x00\x00\x00\x01k\x00\x00\x00\x01x!}",
+        &generated[LLSDBINARYPREFIX.len()..]
+    );
+    assert_eq!(map, LLSDValue::parse(&generated).unwrap());
+    //  Deeper, and as the last item before a terminator.
+    let deep = crate::notation::parse(b"{'a':[!,{'b':!},[!]],'c':!}").unwrap();
+    let generated = to_bytes(&deep).unwrap();
+    assert_eq!(deep, LLSDValue::parse(&generated).unwrap());
+    assert_eq!(
+        deep,
+        parse_read(&mut Cursor::new(&generated[LLSDBINARYPREFIX.len()..])).unwrap()
+    );
+}
+
+#[test]
+fn binarydeepnestingtest() {
+    use crate::NestingTooDeep;
+    //  100,000 nested arrays. Would overflow the stack if parsing recursed.
+    const DEPTH: usize = 100_000;
+    let mut body: Vec<u8> = Vec::new();
+    for _ in 0..DEPTH - 1 {
+        body.extend_from_slice(b"[\x00\x00\x00\x01");
+    }
+    body.extend_from_slice(b"[\x00\x00\x00\x00]"); // innermost, empty
+    body.resize(body.len() + DEPTH - 1, b']');
+    let options = ParseOptions {
+        max_depth: DEPTH,
+        ..Default::default()
+    };
+    let parsed = parse_array_with_options(&body, &options).unwrap();
+    //  Take it apart a level at a time. Dropping it whole would recurse.
+    let mut depth = 0;
+    let mut val = parsed;
+    while let LLSDValue::Array(mut items) = val {
+        depth += 1;
+        val = match items.pop() {
+            Some(inner) => inner,
+            None => break,
+        };
+    }
+    assert_eq!(DEPTH, depth);
+    //  Over the default limit.
+    let err = parse_array(&body).unwrap_err();
+    assert_eq!(
+        Some(&NestingTooDeep {
+            limit: DEFAULT_MAX_DEPTH,
+            position: DEFAULT_MAX_DEPTH * 5
+        }),
+        err.downcast_ref::<NestingTooDeep>()
+    );
+    //  Errors still report the right place. Drop the last terminator.
+    let err = parse_array_with_options(&body[..body.len() - 1], &options)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains(&format!("position {}", body.len() - 1)));
+    body[DEPTH * 5] = b'}';
+    let err = parse_array_with_options(&body, &options)
+        .unwrap_err()
+        .to_string();
+    println!("Error as expected: {}", err);
+    assert!(err.contains("array did not end properly"));
+}
+
+#[test]
+fn binaryisvalidtest() {
+    let val = crate::notation::parse(
+        b"{'a':[i1,r2.5,'three',!,true,u00000000-0000-0000-0000-000000000000],'b':{},'c':b64'AAEC'}",
+    )
+    .unwrap();
+    let generated = to_bytes(&val).unwrap();
+    let body = &generated[LLSDBINARYPREFIX.len()..];
+    assert!(is_valid(&generated));
+    assert!(is_valid(body));
+    //  Scalar, with trailing whitespace.
+    assert!(is_valid(b"i\x00\x00\x00\x05\n"));
+    //  Every truncation is invalid, and agrees with the parser.
+    for n in 0..body.len() {
+        assert!(!is_valid(&body[..n]), "truncated at {}", n);
+        assert!(parse_array(&body[..n]).is_err());
+    }
+    //  Corruption
+    assert!(!is_valid(b"?"));
+    assert!(!is_valid(b"[\x00\x00\x00\x01i\x00\x00\x00\x01}")); // wrong terminator
+    assert!(!is_valid(
+        b"{\x00\x00\x00\x01\x00\x00\x00\x01ai\x00\x00\x00\x01}"
+    )); // no 'k'
+    assert!(!is_valid(b"s\x00\x00\x00\x01\xff")); // bad UTF-8
+    assert!(!is_valid(b"i\x00\x00\x00\x05i")); // trailing data
+    assert!(!is_valid(b"b\xff\xff\xff\xff")); // length past end
+    assert!(!is_valid(b""));
+    //  Nesting, up to the parser's default limit.
+    let nested = |depth: usize| {
+        let mut b = b"[\x00\x00\x00\x01".repeat(depth - 1);
+        b.extend_from_slice(b"[\x00\x00\x00\x00]");
+        b.resize(b.len() + depth - 1, b']');
+        b
+    };
+    assert!(is_valid(&nested(DEFAULT_MAX_DEPTH)));
+    assert!(parse_array(&nested(DEFAULT_MAX_DEPTH)).is_ok());
+    assert!(!is_valid(&nested(DEFAULT_MAX_DEPTH + 1)));
+    assert!(parse_array(&nested(DEFAULT_MAX_DEPTH + 1)).is_err());
+}
+
+#[test]
+fn binaryemptykeytest() {
+    //  A zero-length key is valid, and is the empty string.
+    let body = b"{\x00\x00\x00\x01k\x00\x00\x00\x00i\x00\x00\x00\x01}";
+    let val = LLSDValue::Map(
+        [("".to_string(), LLSDValue::Integer(1))]
+            .iter()
+            .cloned()
+            .collect(),
+    );
+    assert_eq!(val, parse_array(body).unwrap());
+    assert_eq!(
+        val,
+        parse_array_with_options(body, &ParseOptions::strict()).unwrap()
+    );
+    assert!(is_valid(body));
+    let generated = to_bytes(&val).unwrap();
+    assert_eq!(body, &generated[LLSDBINARYPREFIX.len()..]);
+    //  Same as XML.
+    let xml = crate::xml::to_xml_string(&val, false).unwrap();
+    assert_eq!(val, crate::xml::parse(&xml).unwrap());
+}
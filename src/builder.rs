@@ -0,0 +1,87 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Building LLSD maps in code.
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use super::LLSDValue;
+use anyhow::{anyhow, Error};
+use std::collections::HashMap;
+
+/// Builds an LLSD Map, as in
+/// `MapBuilder::new().insert("name", "Vallone").insert("agents", 3).build()`.
+#[derive(Debug, Clone, Default)]
+pub struct MapBuilder {
+    map: HashMap<String, LLSDValue>,
+}
+
+impl MapBuilder {
+    pub fn new() -> MapBuilder {
+        MapBuilder::default()
+    }
+
+    /// Add an entry. A previous entry with the same key is replaced.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<LLSDValue>) -> &mut Self {
+        let _ = self.map.insert(key.into(), value.into());
+        self
+    }
+
+    /// Add an entry. Error if the key is already present, which usually means
+    /// a bug in the code building the map. The existing entry is unchanged.
+    pub fn try_insert(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<LLSDValue>,
+    ) -> Result<&mut Self, Error> {
+        let key = key.into();
+        if self.map.contains_key(&key) {
+            return Err(anyhow!("LLSD MapBuilder: duplicate key {:?}", key));
+        }
+        let _ = self.map.insert(key, value.into());
+        Ok(self)
+    }
+
+    /// The Map. The builder is left empty, ready for reuse.
+    pub fn build(&mut self) -> LLSDValue {
+        LLSDValue::Map(std::mem::take(&mut self.map))
+    }
+}
+
+// Unit tests
+
+#[test]
+fn mapbuildertest() {
+    //  insert replaces.
+    let val = MapBuilder::new()
+        .insert("name", "Vallone")
+        .insert("agents", 3)
+        .insert("agents", 4)
+        .build();
+    assert_eq!(2, val.as_map().unwrap().len());
+    assert_eq!(Some(&LLSDValue::Integer(4)), val.pointer("/agents"));
+    assert_eq!(
+        Some(&LLSDValue::String("Vallone".to_string())),
+        val.pointer("/name")
+    );
+    //  try_insert does not.
+    let mut builder = MapBuilder::new();
+    builder
+        .try_insert("a", 1)
+        .unwrap()
+        .try_insert("b", 2.5)
+        .unwrap();
+    let err = builder.try_insert("a", 3).unwrap_err();
+    println!("Duplicate: {}", err);
+    assert!(err.to_string().contains("\"a\""));
+    let val = builder.build();
+    assert_eq!(Some(&LLSDValue::Integer(1)), val.pointer("/a"));
+    assert_eq!(Some(&LLSDValue::Real(2.5)), val.pointer("/b"));
+    //  Builder is empty after build.
+    assert_eq!(LLSDValue::Map(HashMap::new()), builder.build());
+}
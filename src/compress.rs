@@ -0,0 +1,118 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Compressed LLSD. Optional, with feature "compression".
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use super::{binary, notation, xml, Format, LLSDValue, ParseOptions};
+use anyhow::Error;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+//
+//  Constants
+//
+const GZIPMAGIC: &[u8] = &[0x1f, 0x8b];
+
+impl LLSDValue {
+    /// Parse LLSD which may be gzip or zlib compressed, as capability responses
+    /// often are. Compression is detected from the magic bytes, then the
+    /// format is detected as in `parse`. Uncompressed input is parsed as is.
+    pub fn parse_compressed(msg: &[u8]) -> Result<LLSDValue, Error> {
+        LLSDValue::parse_compressed_with_options(msg, &ParseOptions::default())
+    }
+
+    /// Parse LLSD which may be compressed, with options. `max_size` limits
+    /// the decompressed size, and decompression stops just past it.
+    pub fn parse_compressed_with_options(
+        msg: &[u8],
+        options: &ParseOptions,
+    ) -> Result<LLSDValue, Error> {
+        let limit = u64::try_from(options.max_size)
+            .unwrap_or(u64::MAX)
+            .saturating_add(1);
+        let mut plain = Vec::new();
+        if msg.starts_with(GZIPMAGIC) {
+            GzDecoder::new(msg).take(limit).read_to_end(&mut plain)?;
+        } else if is_zlib_header(msg) {
+            ZlibDecoder::new(msg).take(limit).read_to_end(&mut plain)?;
+        } else {
+            return LLSDValue::parse_with_options(msg, options);
+        }
+        options.check_size(plain.len())?;
+        LLSDValue::parse_with_options(&plain, options)
+    }
+
+    /// Serialize in the given format, with header, then gzip compress.
+    pub fn serialize_compressed(&self, format: Format) -> Result<Vec<u8>, Error> {
+        let plain = match format {
+            Format::Binary => binary::to_bytes(self)?,
+            Format::Xml => xml::to_xml_string(self, false)?.into_bytes(),
+            Format::Notation => notation::to_bytes(self)?,
+        };
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&plain)?;
+        Ok(encoder.finish()?)
+    }
+}
+
+/// A zlib header is CMF, FLG, with deflate method 8 and a header checksum.
+/// Plain LLSD starts with '<', '{', '[', or a notation type code, none of
+/// which has deflate's 8 in the low bits, so this does not misfire.
+fn is_zlib_header(msg: &[u8]) -> bool {
+    match msg {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+// Unit tests
+
+#[test]
+fn compressedtest() {
+    use std::collections::HashMap;
+    let mut map = HashMap::new();
+    let _ = map.insert("name".to_string(), LLSDValue::String("Vallone".to_string()));
+    let _ = map.insert(
+        "grid".to_string(),
+        LLSDValue::Array(vec![LLSDValue::Integer(7); 500]),
+    );
+    let val = LLSDValue::Map(map);
+    //  Binary LLSD, gzip compressed and back.
+    let compressed = val.serialize_compressed(Format::Binary).unwrap();
+    assert!(compressed.starts_with(GZIPMAGIC));
+    assert!(compressed.len() < binary::to_bytes(&val).unwrap().len());
+    assert_eq!(val, LLSDValue::parse_compressed(&compressed).unwrap());
+    //  Other formats.
+    for format in &[Format::Xml, Format::Notation] {
+        let compressed = val.serialize_compressed(*format).unwrap();
+        assert_eq!(val, LLSDValue::parse_compressed(&compressed).unwrap());
+    }
+    //  zlib instead of gzip.
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&binary::to_bytes(&val).unwrap()).unwrap();
+    let zlib = encoder.finish().unwrap();
+    assert_eq!(val, LLSDValue::parse_compressed(&zlib).unwrap());
+    //  Uncompressed input passes through.
+    assert_eq!(
+        val,
+        LLSDValue::parse_compressed(&binary::to_bytes(&val).unwrap()).unwrap()
+    );
+    //  Truncated compressed data is an error.
+    assert!(LLSDValue::parse_compressed(&compressed[..compressed.len() / 2]).is_err());
+    //  A decompression bomb stops at the size limit.
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&vec![b' '; 20_000_000]).unwrap();
+    let bomb = encoder.finish().unwrap();
+    let err = LLSDValue::parse_compressed_with_options(&bomb, &ParseOptions::strict()).unwrap_err();
+    println!("Error as expected: {}", err);
+    assert!(err.downcast_ref::<crate::InputTooLarge>().is_some());
+}
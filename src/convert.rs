@@ -0,0 +1,418 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Conversions between LLSD values and Rust types.
+//
+//  Into LLSD with From, which cannot fail. Out of LLSD with TryFrom,
+//  which fails if the value is of the wrong type.
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use super::xml::{f64_to_xml, format_date};
+use super::{notation, LLSDValue};
+use anyhow::{anyhow, Error};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::iter::FromIterator;
+
+impl LLSDValue {
+    /// UUID value from its text form, such as "67153d5b-3659-afb4-8510-adda2c034649".
+    pub fn uuid_from_str(s: &str) -> Result<LLSDValue, Error> {
+        match uuid::Uuid::parse_str(s.trim()) {
+            Ok(v) => Ok(LLSDValue::UUID(v)),
+            Err(e) => Err(anyhow!("Invalid UUID {:?}: {}", s, e)),
+        }
+    }
+
+    /// UUID value from its 16 bytes.
+    pub fn uuid_from_bytes(b: [u8; 16]) -> LLSDValue {
+        LLSDValue::UUID(uuid::Uuid::from_bytes(b))
+    }
+
+    /// Look up a map field and convert it, as in `val.get_as::<f64>("sim fps")`.
+    /// The error names the field, whether it was missing or of the wrong type.
+    pub fn get_as<T>(&self, key: &str) -> Result<T, Error>
+    where
+        T: for<'a> TryFrom<&'a LLSDValue, Error = Error>,
+    {
+        match self {
+            LLSDValue::Map(m) => match m.get(key) {
+                Some(item) => T::try_from(item).map_err(|e| anyhow!("LLSD field {:?}: {}", key, e)),
+                None => Err(anyhow!("LLSD field {:?} is missing", key)),
+            },
+            _ => Err(anyhow!(
+                "LLSD value is not a map, looking for field {:?}",
+                key
+            )),
+        }
+    }
+
+    /// Convert an Array into a Vec of one type, converting each element.
+    /// The error identifies the first element which would not convert.
+    pub fn to_vec<T>(&self) -> Result<Vec<T>, Error>
+    where
+        T: for<'a> TryFrom<&'a LLSDValue, Error = Error>,
+    {
+        match self {
+            LLSDValue::Array(a) => a
+                .iter()
+                .enumerate()
+                .map(|(n, item)| {
+                    T::try_from(item).map_err(|e| anyhow!("LLSD array element {}: {}", n, e))
+                })
+                .collect(),
+            _ => Err(anyhow!("LLSD value is not an array: {:?}", self)),
+        }
+    }
+
+    /// Bytes of a Binary value. Same as `as_binary`, as a slice.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        self.as_binary().map(|v| v.as_slice())
+    }
+
+    /// Base64 text of a Binary value, as XML LLSD writes it.
+    pub fn to_base64(&self) -> Option<String> {
+        self.as_bytes().map(base64::encode)
+    }
+
+    /// Base16 text of a Binary value, in lower case.
+    pub fn to_base16(&self) -> Option<String> {
+        self.as_bytes().map(hex::encode)
+    }
+
+    /// Base85 text of a Binary value, as XML LLSD writes it, without
+    /// Adobe's `<~` `~>` delimiters.
+    pub fn to_base85(&self) -> Option<String> {
+        self.as_bytes().map(|b| {
+            let text = ascii85::encode(b);
+            text.trim_start_matches("<~")
+                .trim_end_matches("~>")
+                .to_string()
+        })
+    }
+
+    /// Text of any value, for templating. Strings and URIs are borrowed, not copied.
+    /// Other scalars are written as in XML LLSD: "true", "1.5", "nan", the
+    /// hyphenated UUID, the ISO 8601 date, and base64 binary. Undefined is empty.
+    /// Maps and arrays come out in notation, or as "(not printable: ...)"
+    /// with the reason if they hold something notation cannot write.
+    pub fn to_display_str(&self) -> Cow<'_, str> {
+        match self {
+            LLSDValue::String(s) | LLSDValue::URI(s) => Cow::Borrowed(s),
+            LLSDValue::Undefined => Cow::Borrowed(""),
+            LLSDValue::Boolean(v) => Cow::Borrowed(if *v { "true" } else { "false" }),
+            LLSDValue::Integer(v) => Cow::Owned(v.to_string()),
+            LLSDValue::Real(v) => Cow::Owned(f64_to_xml(*v)),
+            LLSDValue::UUID(v) => Cow::Owned(v.to_string()),
+            LLSDValue::Date(v) => {
+                //  A date with no text form shows as its number of seconds.
+                Cow::Owned(format_date(*v).unwrap_or_else(|_| f64_to_xml(*v)))
+            }
+            LLSDValue::Binary(v) => Cow::Owned(base64::encode(v)),
+            LLSDValue::Map(_) | LLSDValue::Array(_) => match notation::to_string(self) {
+                //  Header not wanted here.
+                Ok(text) => Cow::Owned(
+                    text.get(notation::LLSDNOTATIONPREFIX.len()..)
+                        .unwrap_or("")
+                        .to_string(),
+                ),
+                //  Notation cannot write a date outside years 0000 to 9999.
+                Err(e) => Cow::Owned(format!("(not printable: {})", e)),
+            },
+        }
+    }
+}
+
+/// Build an Array from anything convertible to LLSD values.
+impl<T: Into<LLSDValue>> FromIterator<T> for LLSDValue {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        LLSDValue::Array(iter.into_iter().map(|item| item.into()).collect())
+    }
+}
+
+/// Build a Map from key/value pairs. For duplicate keys, the last wins.
+impl FromIterator<(String, LLSDValue)> for LLSDValue {
+    fn from_iter<I: IntoIterator<Item = (String, LLSDValue)>>(iter: I) -> Self {
+        LLSDValue::Map(iter.into_iter().collect())
+    }
+}
+
+//
+//  Rust types into LLSD.
+//
+impl From<bool> for LLSDValue {
+    fn from(v: bool) -> Self {
+        LLSDValue::Boolean(v)
+    }
+}
+
+impl From<i32> for LLSDValue {
+    fn from(v: i32) -> Self {
+        LLSDValue::Integer(v)
+    }
+}
+
+impl From<f64> for LLSDValue {
+    fn from(v: f64) -> Self {
+        LLSDValue::Real(v)
+    }
+}
+
+impl From<String> for LLSDValue {
+    fn from(v: String) -> Self {
+        LLSDValue::String(v)
+    }
+}
+
+impl From<&str> for LLSDValue {
+    fn from(v: &str) -> Self {
+        LLSDValue::String(v.to_string())
+    }
+}
+
+impl From<uuid::Uuid> for LLSDValue {
+    fn from(v: uuid::Uuid) -> Self {
+        LLSDValue::UUID(v)
+    }
+}
+
+impl From<Vec<u8>> for LLSDValue {
+    fn from(v: Vec<u8>) -> Self {
+        LLSDValue::Binary(v)
+    }
+}
+
+impl From<Vec<LLSDValue>> for LLSDValue {
+    fn from(v: Vec<LLSDValue>) -> Self {
+        LLSDValue::Array(v)
+    }
+}
+
+impl From<HashMap<String, LLSDValue>> for LLSDValue {
+    fn from(v: HashMap<String, LLSDValue>) -> Self {
+        LLSDValue::Map(v)
+    }
+}
+
+//
+//  LLSD into Rust types.
+//
+/// Error for a value of the wrong type.
+fn wrong_type(wanted: &str, val: &LLSDValue) -> Error {
+    anyhow!("Expected LLSD {}, found {:?}", wanted, val)
+}
+
+impl TryFrom<&LLSDValue> for bool {
+    type Error = Error;
+    fn try_from(val: &LLSDValue) -> Result<Self, Self::Error> {
+        match val {
+            LLSDValue::Boolean(v) => Ok(*v),
+            _ => Err(wrong_type("boolean", val)),
+        }
+    }
+}
+
+impl TryFrom<&LLSDValue> for i32 {
+    type Error = Error;
+    fn try_from(val: &LLSDValue) -> Result<Self, Self::Error> {
+        match val {
+            LLSDValue::Integer(v) => Ok(*v),
+            _ => Err(wrong_type("integer", val)),
+        }
+    }
+}
+
+/// Integers, and reals which are whole numbers, as with `as_i64`.
+impl TryFrom<&LLSDValue> for i64 {
+    type Error = Error;
+    fn try_from(val: &LLSDValue) -> Result<Self, Self::Error> {
+        val.as_i64().ok_or_else(|| wrong_type("integer", val))
+    }
+}
+
+/// Integers, and reals which are whole numbers, as with `as_u32`.
+impl TryFrom<&LLSDValue> for u32 {
+    type Error = Error;
+    fn try_from(val: &LLSDValue) -> Result<Self, Self::Error> {
+        val.as_u32()
+            .ok_or_else(|| wrong_type("unsigned integer", val))
+    }
+}
+
+/// Reals, and integers widened, as with `as_f64`.
+impl TryFrom<&LLSDValue> for f64 {
+    type Error = Error;
+    fn try_from(val: &LLSDValue) -> Result<Self, Self::Error> {
+        val.as_f64().ok_or_else(|| wrong_type("real", val))
+    }
+}
+
+impl TryFrom<&LLSDValue> for String {
+    type Error = Error;
+    fn try_from(val: &LLSDValue) -> Result<Self, Self::Error> {
+        match val {
+            LLSDValue::String(v) => Ok(v.clone()),
+            _ => Err(wrong_type("string", val)),
+        }
+    }
+}
+
+impl TryFrom<&LLSDValue> for uuid::Uuid {
+    type Error = Error;
+    fn try_from(val: &LLSDValue) -> Result<Self, Self::Error> {
+        match val {
+            LLSDValue::UUID(v) => Ok(*v),
+            _ => Err(wrong_type("uuid", val)),
+        }
+    }
+}
+
+// Unit tests
+
+#[test]
+fn tovectest() {
+    let ints = LLSDValue::Array(vec![
+        LLSDValue::Integer(1),
+        LLSDValue::Integer(2),
+        LLSDValue::Integer(3),
+    ]);
+    assert_eq!(vec![1, 2, 3], ints.to_vec::<i32>().unwrap());
+    assert_eq!(vec![1.0, 2.0, 3.0], ints.to_vec::<f64>().unwrap());
+    //  Mixed types fail, naming the element.
+    let mixed = LLSDValue::Array(vec![
+        LLSDValue::Integer(1),
+        LLSDValue::String("2".to_string()),
+    ]);
+    let err = mixed.to_vec::<i32>().unwrap_err().to_string();
+    println!("Error as expected: {}", err);
+    assert!(err.contains("element 1"));
+    assert!(LLSDValue::Integer(1).to_vec::<i32>().is_err());
+    //  And back.
+    assert_eq!(ints, vec![1, 2, 3].into_iter().collect::<LLSDValue>());
+    let ids = vec![uuid::Uuid::nil(), uuid::Uuid::nil()];
+    let idarray = LLSDValue::from_iter(ids.clone());
+    assert_eq!(ids, idarray.to_vec::<uuid::Uuid>().unwrap());
+    assert_eq!(LLSDValue::String("a".to_string()), LLSDValue::from("a"));
+}
+
+#[test]
+fn uuidconstructortest() {
+    let v = LLSDValue::uuid_from_str("67153d5b-3659-afb4-8510-adda2c034649").unwrap();
+    assert_eq!(
+        "67153d5b-3659-afb4-8510-adda2c034649",
+        v.as_uuid().unwrap().to_string()
+    );
+    let err = LLSDValue::uuid_from_str("67153d5b-not-a-uuid").unwrap_err();
+    println!("Error as expected: {}", err);
+    assert!(err.to_string().contains("Invalid UUID"));
+    let b = *v.as_uuid().unwrap().as_bytes();
+    assert_eq!(v, LLSDValue::uuid_from_bytes(b));
+    assert_eq!(
+        LLSDValue::UUID(uuid::Uuid::nil()),
+        LLSDValue::uuid_from_bytes([0; 16])
+    );
+}
+
+#[test]
+fn todisplaystrtest() {
+    let string = LLSDValue::String("Vallone".to_string());
+    assert!(matches!(string.to_display_str(), Cow::Borrowed("Vallone")));
+    let uri = LLSDValue::URI("http://example.com".to_string());
+    assert!(matches!(
+        uri.to_display_str(),
+        Cow::Borrowed("http://example.com")
+    ));
+    assert!(matches!(LLSDValue::Integer(-42).to_display_str(), Cow::Owned(s) if s == "-42"));
+    assert_eq!("1.5", LLSDValue::Real(1.5).to_display_str());
+    assert_eq!("nan", LLSDValue::Real(f64::NAN).to_display_str());
+    assert_eq!("true", LLSDValue::Boolean(true).to_display_str());
+    assert_eq!("", LLSDValue::Undefined.to_display_str());
+    assert_eq!(
+        "67153d5b-3659-afb4-8510-adda2c034649",
+        LLSDValue::uuid_from_str("67153d5b-3659-afb4-8510-adda2c034649")
+            .unwrap()
+            .to_display_str()
+    );
+    assert_eq!(
+        "2006-02-01T14:29:53Z",
+        LLSDValue::Date(1138804193.0).to_display_str()
+    );
+    assert_eq!("AAH/", LLSDValue::Binary(vec![0, 1, 255]).to_display_str());
+    assert_eq!(
+        "[i1,i2]",
+        LLSDValue::Array(vec![1.into(), 2.into()]).to_display_str()
+    );
+    let unprintable = LLSDValue::Array(vec![LLSDValue::Date(1e300)])
+        .to_display_str()
+        .into_owned();
+    println!("Unprintable: {}", unprintable);
+    assert!(unprintable.starts_with("(not printable: "));
+}
+
+#[test]
+fn fromiteratortest() {
+    //  A range into an integer array.
+    let arr: LLSDValue = (1..=3).map(LLSDValue::Integer).collect();
+    assert_eq!(
+        LLSDValue::Array(vec![
+            LLSDValue::Integer(1),
+            LLSDValue::Integer(2),
+            LLSDValue::Integer(3)
+        ]),
+        arr
+    );
+    let arr: LLSDValue = vec![1.5, 2.5].into_iter().map(LLSDValue::from).collect();
+    assert_eq!(Some(&LLSDValue::Real(2.5)), arr.pointer("/1"));
+    //  Pairs into a map.
+    let map: LLSDValue = vec!["a", "b", "a"]
+        .into_iter()
+        .enumerate()
+        .map(|(n, key)| (key.to_string(), LLSDValue::from(n as i32)))
+        .collect();
+    assert_eq!(2, map.as_map().unwrap().len());
+    assert_eq!(Some(&LLSDValue::Integer(1)), map.pointer("/b"));
+    assert_eq!(Some(&LLSDValue::Integer(2)), map.pointer("/a")); // last wins
+}
+
+#[test]
+fn binaryencodetest() {
+    let val = LLSDValue::Binary(b"Hello, world".to_vec());
+    assert_eq!(Some(&b"Hello, world"[..]), val.as_bytes());
+    assert_eq!(Some("SGVsbG8sIHdvcmxk".to_string()), val.to_base64());
+    assert_eq!(
+        Some("48656c6c6f2c20776f726c64".to_string()),
+        val.to_base16()
+    );
+    assert_eq!(Some("87cURD_*#TDfTZ)".to_string()), val.to_base85());
+    //  Each must read back in XML.
+    for (encoding, text) in [
+        ("base64", val.to_base64()),
+        ("base16", val.to_base16()),
+        ("base85", val.to_base85()),
+    ]
+    .iter()
+    {
+        let doc = format!(
+            "<?xml version=\"1.0\" ?><llsd><binary encoding=\"{}\">{}</binary></llsd>",
+            encoding,
+            text.as_ref().unwrap()
+        );
+        assert_eq!(val, super::xml::parse(&doc).unwrap());
+    }
+    //  Not binary.
+    let s = LLSDValue::String("Hello".to_string());
+    assert_eq!(None, s.as_bytes());
+    assert_eq!(None, s.to_base64());
+    assert_eq!(None, s.to_base16());
+    assert_eq!(None, s.to_base85());
+    assert_eq!(
+        Some("".to_string()),
+        LLSDValue::Binary(Vec::new()).to_base64()
+    );
+}
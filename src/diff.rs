@@ -0,0 +1,177 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Differences between two LLSD trees.
+//
+//  A patch is itself an LLSD value, so it can be sent with any of the
+//  serializers. It is an array of operations, each a map:
+//
+//      {'op': 'add' | 'remove' | 'change', 'path': '/a/b', 'value': ...}
+//
+//  Paths are in the syntax of LLSDValue::pointer. 'remove' has no value.
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use super::{escape_path_key, sorted_map_entries, LLSDValue};
+use anyhow::{anyhow, Error};
+use std::collections::HashMap;
+
+/// A patch, as produced by `diff`. An LLSD array of operations.
+pub type LLSDPatch = LLSDValue;
+
+/// Compute the changes which turn `old` into `new`.
+///
+/// Maps are compared key by key, and arrays of the same length element
+/// by element. An array which changed length is replaced as a whole.
+pub fn diff(old: &LLSDValue, new: &LLSDValue) -> LLSDPatch {
+    let mut ops = Vec::new();
+    diff_value(old, new, "", &mut ops);
+    LLSDValue::Array(ops)
+}
+
+/// Apply a patch from `diff`, in place.
+pub fn apply(base: &mut LLSDValue, patch: &LLSDPatch) -> Result<(), Error> {
+    let ops = match patch.as_array() {
+        Some(ops) => ops,
+        None => return Err(anyhow!("LLSD patch is not an array")),
+    };
+    for op in ops {
+        let path = match op.as_map().and_then(|m| m.get("path")) {
+            Some(LLSDValue::String(path)) => path,
+            _ => return Err(anyhow!("LLSD patch operation has no path: {:?}", op)),
+        };
+        match op.as_map().and_then(|m| m.get("op")) {
+            Some(LLSDValue::String(kind)) if kind == "add" || kind == "change" => {
+                let value = match op.as_map().and_then(|m| m.get("value")) {
+                    Some(value) => value.clone(),
+                    None => return Err(anyhow!("LLSD patch {} at {:?} has no value", kind, path)),
+                };
+                base.set_path(path, value)?
+            }
+            Some(LLSDValue::String(kind)) if kind == "remove" => remove_path(base, path)?,
+            _ => return Err(anyhow!("LLSD patch operation not recognized: {:?}", op)),
+        }
+    }
+    Ok(())
+}
+
+/// Compare one value. Recursive.
+fn diff_value(old: &LLSDValue, new: &LLSDValue, path: &str, ops: &mut Vec<LLSDValue>) {
+    match (old, new) {
+        (LLSDValue::Map(oldmap), LLSDValue::Map(newmap)) => {
+            for (key, oldval) in sorted_map_entries(oldmap) {
+                let keypath = format!("{}/{}", path, escape_path_key(key));
+                match newmap.get(key) {
+                    Some(newval) => diff_value(oldval, newval, &keypath, ops),
+                    None => ops.push(make_op("remove", keypath, None)),
+                }
+            }
+            for (key, newval) in sorted_map_entries(newmap) {
+                if !oldmap.contains_key(key) {
+                    let keypath = format!("{}/{}", path, escape_path_key(key));
+                    ops.push(make_op("add", keypath, Some(newval)));
+                }
+            }
+        }
+        (LLSDValue::Array(oldarray), LLSDValue::Array(newarray))
+            if oldarray.len() == newarray.len() =>
+        {
+            for (n, (oldval, newval)) in oldarray.iter().zip(newarray).enumerate() {
+                diff_value(oldval, newval, &format!("{}/{}", path, n), ops);
+            }
+        }
+        _ => {
+            if old != new {
+                ops.push(make_op("change", path.to_string(), Some(new)));
+            }
+        }
+    }
+}
+
+/// One patch operation.
+fn make_op(kind: &str, path: String, value: Option<&LLSDValue>) -> LLSDValue {
+    let mut op = HashMap::new();
+    op.insert("op".to_string(), LLSDValue::String(kind.to_string()));
+    op.insert("path".to_string(), LLSDValue::String(path));
+    if let Some(value) = value {
+        op.insert("value".to_string(), value.clone());
+    }
+    LLSDValue::Map(op)
+}
+
+/// Remove the map entry at the end of a path.
+fn remove_path(base: &mut LLSDValue, path: &str) -> Result<(), Error> {
+    let (parent, key) = match path.rfind('/') {
+        Some(n) => (
+            &path[..n],
+            path[n + 1..].replace("~1", "/").replace("~0", "~"),
+        ),
+        None => return Err(anyhow!("LLSD patch cannot remove {:?}", path)),
+    };
+    match base.pointer_mut(parent) {
+        Some(LLSDValue::Map(m)) => {
+            let _ = m.remove(&key);
+            Ok(())
+        }
+        _ => Err(anyhow!("LLSD patch remove: no map at {:?}", parent)),
+    }
+}
+
+// Unit tests
+
+#[test]
+fn difftest() {
+    const OLDXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+  <key>scale</key><string>one minute</string>
+  <key>removed</key><integer>1</integer>
+  <key>stats</key>
+  <map>
+    <key>sim fps</key><real>44.5</real>
+    <key>a/b</key><integer>2</integer>
+    <key>array</key><array><integer>1</integer><integer>2</integer></array>
+    <key>resized</key><array><integer>1</integer></array>
+  </map>
+</map>
+</llsd>
+"#;
+    const NEWXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+  <key>scale</key><string>one minute</string>
+  <key>added</key><map><key>x</key><integer>3</integer></map>
+  <key>stats</key>
+  <map>
+    <key>sim fps</key><real>45.5</real>
+    <key>a/b</key><integer>3</integer>
+    <key>array</key><array><integer>1</integer><integer>5</integer></array>
+    <key>resized</key><array><integer>1</integer><integer>2</integer></array>
+  </map>
+</map>
+</llsd>
+"#;
+    let old = crate::xml::parse(OLDXML).unwrap();
+    let new = crate::xml::parse(NEWXML).unwrap();
+    let patch = diff(&old, &new);
+    println!("Patch: {:#?}", patch);
+    assert_eq!(6, patch.as_array().unwrap().len());
+    //  Send the patch through a serializer.
+    let patch = LLSDValue::parse(&crate::binary::to_bytes(&patch).unwrap()).unwrap();
+    let mut patched = old.clone();
+    apply(&mut patched, &patch).unwrap();
+    assert_eq!(new, patched);
+    //  No differences, no operations.
+    assert_eq!(LLSDValue::Array(Vec::new()), diff(&new, &new));
+    //  Change at top level.
+    let mut scalar = LLSDValue::Integer(1);
+    apply(&mut scalar, &diff(&LLSDValue::Integer(1), &new)).unwrap();
+    assert_eq!(new, scalar);
+    //  Bad patch
+    assert!(apply(&mut patched, &LLSDValue::Integer(1)).is_err());
+}
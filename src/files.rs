@@ -0,0 +1,82 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Batch reading of LLSD files, such as captured capability traffic.
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use super::LLSDValue;
+use anyhow::{anyhow, Error};
+use std::path::{Path, PathBuf};
+
+/// Parse each file in a directory, detecting format, in file name order.
+/// Not recursive; subdirectories are skipped. Each result is paired with its
+/// path, so one bad file does not stop the rest. If the directory itself
+/// cannot be read, that is the one error returned, with the directory's path.
+pub fn walk_llsd_dir<P: AsRef<Path>>(
+    dir: P,
+) -> impl Iterator<Item = (PathBuf, Result<LLSDValue, Error>)> {
+    let dir = dir.as_ref().to_path_buf();
+    let (paths, err) = match list_files(&dir) {
+        Ok(paths) => (paths, None),
+        Err(e) => (Vec::new(), Some((dir, Err(e)))),
+    };
+    err.into_iter().chain(paths.into_iter().map(|path| {
+        let result = std::fs::read(&path)
+            .map_err(Error::from)
+            .and_then(|bytes| LLSDValue::parse(&bytes));
+        (path, result)
+    }))
+}
+
+/// Files in a directory, sorted.
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = Vec::new();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return Err(anyhow!("Cannot read directory {:?}: {}", dir, e)),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+// Unit tests
+
+#[test]
+fn walkllsddirtest() {
+    use super::{binary, xml};
+    let dir = std::env::temp_dir().join(format!("llsd-walk-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("subdir")).unwrap();
+    let val = LLSDValue::Array(vec![
+        LLSDValue::Integer(1),
+        LLSDValue::String("two".to_string()),
+    ]);
+    std::fs::write(dir.join("a.xml"), xml::to_xml_string(&val, true).unwrap()).unwrap();
+    std::fs::write(dir.join("b.llsd"), binary::to_bytes(&val).unwrap()).unwrap();
+    std::fs::write(dir.join("c.txt"), "not LLSD").unwrap();
+    let results: Vec<(PathBuf, Result<LLSDValue, Error>)> = walk_llsd_dir(&dir).collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let names: Vec<String> = results
+        .iter()
+        .map(|(path, _)| path.file_name().unwrap().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(vec!["a.xml", "b.llsd", "c.txt"], names);
+    assert_eq!(&val, results[0].1.as_ref().unwrap());
+    assert_eq!(&val, results[1].1.as_ref().unwrap());
+    assert!(results[2].1.is_err());
+    //  Missing directory
+    let results: Vec<_> = walk_llsd_dir(dir.join("nonexistent")).collect();
+    assert_eq!(1, results.len());
+    assert!(results[0].1.is_err());
+}
@@ -0,0 +1,140 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Second Life geometry conventions.
+//
+//  SL sends vectors as an array of 3 reals, [x, y, z], and rotations
+//  as a quaternion in an array of 4 reals, [x, y, z, s], with the
+//  scalar part last.
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use super::LLSDValue;
+use anyhow::{anyhow, Error};
+
+/// A 3D vector, such as a position, velocity, or scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vector3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Vector3 {
+    /// From an LLSD array of 3 reals.
+    pub fn from_llsd(val: &LLSDValue) -> Result<Vector3, Error> {
+        let [x, y, z] = reals::<3>(val, "Vector3")?;
+        Ok(Vector3 { x, y, z })
+    }
+
+    /// To an LLSD array of 3 reals.
+    pub fn to_llsd(&self) -> LLSDValue {
+        LLSDValue::Array(vec![
+            LLSDValue::Real(self.x),
+            LLSDValue::Real(self.y),
+            LLSDValue::Real(self.z),
+        ])
+    }
+}
+
+/// A rotation. `s` is the scalar part, which SL puts last.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub s: f64,
+}
+
+impl Quaternion {
+    /// From an LLSD array of 4 reals, [x, y, z, s].
+    pub fn from_llsd(val: &LLSDValue) -> Result<Quaternion, Error> {
+        let [x, y, z, s] = reals::<4>(val, "Quaternion")?;
+        Ok(Quaternion { x, y, z, s })
+    }
+
+    /// To an LLSD array of 4 reals, [x, y, z, s].
+    pub fn to_llsd(&self) -> LLSDValue {
+        LLSDValue::Array(vec![
+            LLSDValue::Real(self.x),
+            LLSDValue::Real(self.y),
+            LLSDValue::Real(self.z),
+            LLSDValue::Real(self.s),
+        ])
+    }
+}
+
+/// An array of exactly N reals, with an error message saying what is wrong.
+fn reals<const N: usize>(val: &LLSDValue, what: &str) -> Result<[f64; N], Error> {
+    let items = match val {
+        LLSDValue::Array(items) => items,
+        _ => return Err(anyhow!("LLSD {} must be an array, not {:?}", what, val)),
+    };
+    if items.len() != N {
+        return Err(anyhow!(
+            "LLSD {} must have {} elements, found {}",
+            what,
+            N,
+            items.len()
+        ));
+    }
+    let mut out = [0.0; N];
+    for (n, (v, item)) in out.iter_mut().zip(items).enumerate() {
+        *v = match item {
+            LLSDValue::Real(r) => *r,
+            _ => {
+                return Err(anyhow!(
+                    "LLSD {} element {} is not a real: {:?}",
+                    what,
+                    n,
+                    item
+                ))
+            }
+        };
+    }
+    Ok(out)
+}
+
+// Unit tests
+
+#[test]
+fn geometrytest() {
+    let v = Vector3 {
+        x: 128.0,
+        y: 64.5,
+        z: -22.25,
+    };
+    let llsd = v.to_llsd();
+    assert_eq!(
+        LLSDValue::Array(vec![
+            LLSDValue::Real(128.0),
+            LLSDValue::Real(64.5),
+            LLSDValue::Real(-22.25)
+        ]),
+        llsd
+    );
+    assert_eq!(v, Vector3::from_llsd(&llsd).unwrap());
+    let q = Quaternion {
+        x: 0.0,
+        y: 0.0,
+        z: std::f64::consts::FRAC_1_SQRT_2,
+        s: std::f64::consts::FRAC_1_SQRT_2,
+    };
+    assert_eq!(q, Quaternion::from_llsd(&q.to_llsd()).unwrap());
+    //  Through a serialized form.
+    let parsed = LLSDValue::parse(&super::binary::to_bytes(&q.to_llsd()).unwrap()).unwrap();
+    assert_eq!(q, Quaternion::from_llsd(&parsed).unwrap());
+    //  Errors
+    let err = Vector3::from_llsd(&q.to_llsd()).unwrap_err();
+    println!("Wrong length: {}", err);
+    assert!(err.to_string().contains("must have 3 elements, found 4"));
+    assert!(Quaternion::from_llsd(&v.to_llsd()).is_err());
+    let ints = LLSDValue::Array(vec![LLSDValue::Integer(1); 3]);
+    assert!(Vector3::from_llsd(&ints).is_err());
+    assert!(Vector3::from_llsd(&LLSDValue::Real(1.0)).is_err());
+}
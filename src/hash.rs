@@ -0,0 +1,51 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Content hashes. Optional, with feature "hash".
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use super::binary;
+use super::LLSDValue;
+use anyhow::Error;
+use sha2::{Digest, Sha256};
+
+impl LLSDValue {
+    /// SHA-256 of the canonical binary form. Values which are equal have the
+    /// same hash, regardless of map insertion order, in any process.
+    /// Fails only if the value is too large for binary LLSD.
+    pub fn content_hash(&self) -> Result<[u8; 32], Error> {
+        let canonical = binary::to_bytes_canonical(self)?;
+        Ok(Sha256::digest(&canonical).into())
+    }
+}
+
+// Unit tests
+
+#[test]
+fn contenthashtest() {
+    let entries = [
+        ("a".to_string(), LLSDValue::Integer(1)),
+        ("b".to_string(), LLSDValue::Real(f64::NAN)),
+        ("c".to_string(), LLSDValue::String("three".to_string())),
+    ];
+    let forward: std::collections::HashMap<String, LLSDValue> = entries.iter().cloned().collect();
+    let mut backward = std::collections::HashMap::new();
+    for (key, value) in entries.iter().rev() {
+        let _ = backward.insert(key.clone(), value.clone());
+    }
+    let hash1 = LLSDValue::Map(forward).content_hash().unwrap();
+    let hash2 = LLSDValue::Map(backward).content_hash().unwrap();
+    assert_eq!(hash1, hash2);
+    assert_ne!(hash1, LLSDValue::Integer(1).content_hash().unwrap());
+    //  -0.0 == 0.0, so they must hash alike.
+    assert_eq!(
+        LLSDValue::Real(0.0).content_hash().unwrap(),
+        LLSDValue::Real(-0.0).content_hash().unwrap()
+    );
+}
@@ -0,0 +1,206 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Conversion to JSON. Optional, with feature "json".
+//
+//  This is one-way and lossy. JSON has no UUID, date, URI, or binary
+//  types, so those become strings.
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use super::xml::format_date;
+use super::LLSDValue;
+use anyhow::{anyhow, Error};
+use serde_json::{Map, Number, Value};
+//
+//  Constants
+//
+const DEBUGBINARYLIMIT: usize = 64; // show at most this many bytes of a blob in debug output
+
+/// What to do with NaN and infinite reals, which JSON cannot represent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NonFinitePolicy {
+    /// null, as serde_json does.
+    Null,
+    /// The strings "NaN", "Infinity", and "-Infinity", as JavaScript prints them.
+    String,
+    /// Fail the conversion.
+    Error,
+}
+
+/// Options for JSON conversion.
+#[derive(Debug, Clone)]
+pub struct JsonOptions {
+    pub nonfinite: NonFinitePolicy,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        JsonOptions {
+            nonfinite: NonFinitePolicy::Null,
+        }
+    }
+}
+
+/// Convert an LLSDValue to a serde_json Value.
+/// UUIDs, dates, and URIs become strings, binary becomes base64,
+/// and Undefined becomes null. Non-finite reals also become null,
+/// as with serde_json, as do dates outside years 0000 to 9999.
+pub fn to_json(val: &LLSDValue) -> Value {
+    //  Only the Error policy can fail.
+    to_json_with_options(val, &JsonOptions::default()).unwrap_or(Value::Null)
+}
+
+/// Convert an LLSDValue to a serde_json Value, with options.
+/// Fails only for a non-finite real under NonFinitePolicy::Error.
+pub fn to_json_with_options(val: &LLSDValue, options: &JsonOptions) -> Result<Value, Error> {
+    convert_value(val, options, false)
+}
+
+impl LLSDValue {
+    /// Pretty-printed JSON, for looking at a value during debugging.
+    /// Unlike `to_json`, large binary blobs are truncated with a note of their
+    /// length, and non-finite reals are shown as strings.
+    pub fn to_debug_json(&self) -> String {
+        //  Serializing a Value cannot fail.
+        let options = JsonOptions {
+            nonfinite: NonFinitePolicy::String,
+        };
+        match convert_value(self, &options, true) {
+            Ok(v) => serde_json::to_string_pretty(&v).unwrap_or_default(),
+            Err(_) => String::new(), // String policy cannot fail
+        }
+    }
+}
+
+/// Convert one value. Recursive.
+fn convert_value(val: &LLSDValue, options: &JsonOptions, debug: bool) -> Result<Value, Error> {
+    Ok(match val {
+        LLSDValue::Undefined => Value::Null,
+        LLSDValue::Boolean(v) => Value::Bool(*v),
+        LLSDValue::Integer(v) => Value::Number((*v).into()),
+        LLSDValue::Real(v) => match Number::from_f64(*v) {
+            Some(n) => Value::Number(n),
+            None => match options.nonfinite {
+                NonFinitePolicy::Null => Value::Null,
+                NonFinitePolicy::String => Value::String(
+                    if v.is_nan() {
+                        "NaN"
+                    } else if *v > 0.0 {
+                        "Infinity"
+                    } else {
+                        "-Infinity"
+                    }
+                    .to_string(),
+                ),
+                NonFinitePolicy::Error => {
+                    return Err(anyhow!("LLSD real {} cannot be represented in JSON", v))
+                }
+            },
+        },
+        LLSDValue::UUID(v) => Value::String(v.to_string()),
+        LLSDValue::String(v) => Value::String(v.clone()),
+        LLSDValue::URI(v) => Value::String(v.clone()),
+        LLSDValue::Date(v) => match format_date(*v) {
+            Ok(text) => Value::String(text),
+            Err(_) => Value::Null, // no RFC 3339 form
+        },
+        LLSDValue::Binary(v) => {
+            if debug && v.len() > DEBUGBINARYLIMIT {
+                Value::String(format!(
+                    "{}... ({} bytes)",
+                    base64::encode(&v[..DEBUGBINARYLIMIT]),
+                    v.len()
+                ))
+            } else {
+                Value::String(base64::encode(v))
+            }
+        }
+        LLSDValue::Map(v) => Value::Object(
+            v.iter()
+                .map(|(key, value)| Ok((key.clone(), convert_value(value, options, debug)?)))
+                .collect::<Result<Map<String, Value>, Error>>()?,
+        ),
+        LLSDValue::Array(v) => Value::Array(
+            v.iter()
+                .map(|value| convert_value(value, options, debug))
+                .collect::<Result<Vec<Value>, Error>>()?,
+        ),
+    })
+}
+
+// Unit tests
+
+#[test]
+fn jsondebugtest() {
+    let test1 = LLSDValue::Map(
+        [
+            ("region_id".to_string(), LLSDValue::UUID(uuid::Uuid::nil())),
+            ("sim fps".to_string(), LLSDValue::Real(44.5)),
+            ("bad".to_string(), LLSDValue::Real(f64::NAN)),
+            ("blob".to_string(), LLSDValue::Binary(vec![0u8; 1000])),
+            (
+                "array".to_string(),
+                LLSDValue::Array(vec![LLSDValue::Integer(42), LLSDValue::Undefined]),
+            ),
+        ]
+        .iter()
+        .cloned()
+        .collect(),
+    );
+    let s = test1.to_debug_json();
+    println!("Debug JSON: {}", s);
+    let parsed: Value = serde_json::from_str(&s).unwrap(); // must be valid JSON
+    assert_eq!(
+        Some("00000000-0000-0000-0000-000000000000"),
+        parsed["region_id"].as_str()
+    );
+    assert_eq!(Some(44.5), parsed["sim fps"].as_f64());
+    assert_eq!(Some("NaN"), parsed["bad"].as_str());
+    assert!(parsed["blob"].as_str().unwrap().ends_with("(1000 bytes)"));
+    assert_eq!(Some(42), parsed["array"][0].as_i64());
+    //  Pretty-printed.
+    assert!(s.contains('\n'));
+    //  The plain bridge keeps all the data and uses null for NaN.
+    let plain = to_json(&test1);
+    assert_eq!(Value::Null, plain["bad"]);
+    assert_eq!(
+        1000,
+        base64::decode(plain["blob"].as_str().unwrap())
+            .unwrap()
+            .len()
+    );
+}
+
+#[test]
+fn jsonnonfinitetest() {
+    let test1 = LLSDValue::Array(vec![
+        LLSDValue::Real(1.5),
+        LLSDValue::Real(f64::NAN),
+        LLSDValue::Real(f64::INFINITY),
+        LLSDValue::Real(f64::NEG_INFINITY),
+    ]);
+    let convert = |nonfinite| to_json_with_options(&test1, &JsonOptions { nonfinite });
+    //  Null, the default.
+    let nulls = convert(NonFinitePolicy::Null).unwrap();
+    assert_eq!(serde_json::json!([1.5, null, null, null]), nulls);
+    assert_eq!(nulls, to_json(&test1));
+    //  String
+    assert_eq!(
+        serde_json::json!([1.5, "NaN", "Infinity", "-Infinity"]),
+        convert(NonFinitePolicy::String).unwrap()
+    );
+    //  Error
+    let err = convert(NonFinitePolicy::Error).unwrap_err();
+    println!("Nonfinite error: {}", err);
+    assert!(err.to_string().contains("NaN"));
+    let strict = JsonOptions {
+        nonfinite: NonFinitePolicy::Error,
+    };
+    assert!(to_json_with_options(&LLSDValue::Real(2.0), &strict).is_ok());
+}
@@ -11,16 +11,53 @@
 //
 //  Modules
 //
+#[macro_use]
+#[doc(hidden)]
+pub mod macros;
+#[cfg(feature = "arena")]
+mod arena;
 pub mod binary;
+pub mod builder;
+#[cfg(feature = "compression")]
+mod compress;
+mod convert;
+pub mod diff;
+pub mod files;
+pub mod geometry;
+#[cfg(feature = "hash")]
+mod hash;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod migrate;
+pub mod notation;
+#[cfg(feature = "sign")]
+pub mod sign;
 pub mod xml;
 //
 use anyhow::{anyhow, Error};
-use std::collections::HashMap;
-use uuid;
 use enum_as_inner::EnumAsInner;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Read;
+// So users need not depend on uuid to name uuid::Uuid.
+pub use uuid;
 //
 ///  The primitive LLSD data item.
-#[derive(Debug, Clone, PartialEq, EnumAsInner)]
+///
+///  Equality is as for the Rust types, except for reals and dates, where
+///  all NaNs are equal to each other, and -0.0 equals 0.0 as usual. That makes
+///  `==` an equivalence, so values implement `Eq` and `Hash` and can go in a
+///  HashSet or be HashMap keys. Hashing treats -0.0 as 0.0, and all NaNs alike,
+///  to agree with `==`. Maps hash the same regardless of insertion order.
+///
+///  The empty string is a valid map key. Every format reads and writes it,
+///  so `<key></key>` in XML and a zero-length key in binary both give `""`.
+///
+///  Dropping, cloning, comparing, and serializing a value recurse. The parsers
+///  stop at `DEFAULT_MAX_DEPTH`; raising `ParseOptions::max_depth` far past
+///  that allows values too deep for those operations on a normal stack.
+#[derive(Debug, Clone, EnumAsInner)]
 pub enum LLSDValue {
     Undefined,
     Boolean(bool),
@@ -28,47 +65,1164 @@ pub enum LLSDValue {
     Integer(i32),
     UUID(uuid::Uuid),
     String(String),
-    Date(i64),
+    Date(f64),
     URI(String),
     Binary(Vec<u8>),
     Map(HashMap<String, LLSDValue>),
     Array(Vec<LLSDValue>),
 }
 
+impl PartialEq for LLSDValue {
+    fn eq(&self, other: &LLSDValue) -> bool {
+        match (self, other) {
+            (LLSDValue::Undefined, LLSDValue::Undefined) => true,
+            (LLSDValue::Boolean(a), LLSDValue::Boolean(b)) => a == b,
+            (LLSDValue::Real(a), LLSDValue::Real(b)) => real_eq(*a, *b),
+            (LLSDValue::Integer(a), LLSDValue::Integer(b)) => a == b,
+            (LLSDValue::UUID(a), LLSDValue::UUID(b)) => a == b,
+            (LLSDValue::String(a), LLSDValue::String(b)) => a == b,
+            (LLSDValue::Date(a), LLSDValue::Date(b)) => real_eq(*a, *b),
+            (LLSDValue::URI(a), LLSDValue::URI(b)) => a == b,
+            (LLSDValue::Binary(a), LLSDValue::Binary(b)) => a == b,
+            (LLSDValue::Map(a), LLSDValue::Map(b)) => a == b,
+            (LLSDValue::Array(a), LLSDValue::Array(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for LLSDValue {}
+
+impl std::hash::Hash for LLSDValue {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            LLSDValue::Undefined => {}
+            LLSDValue::Boolean(v) => v.hash(state),
+            LLSDValue::Real(v) | LLSDValue::Date(v) => canonical_real_bits(*v).hash(state),
+            LLSDValue::Integer(v) => v.hash(state),
+            LLSDValue::UUID(v) => v.hash(state),
+            LLSDValue::String(v) | LLSDValue::URI(v) => v.hash(state),
+            LLSDValue::Binary(v) => v.hash(state),
+            LLSDValue::Map(v) => {
+                v.len().hash(state);
+                for (key, value) in sorted_map_entries(v) {
+                    key.hash(state);
+                    value.hash(state);
+                }
+            }
+            LLSDValue::Array(v) => v.hash(state),
+        }
+    }
+}
+
+/// Equality of reals, with NaN equal to NaN.
+fn real_eq(a: f64, b: f64) -> bool {
+    a == b || (a.is_nan() && b.is_nan())
+}
+
+/// Bits of a real for hashing and canonical output, consistent with real_eq.
+pub(crate) fn canonical_real_bits(v: f64) -> u64 {
+    if v.is_nan() {
+        f64::NAN.to_bits()
+    } else if v == 0.0 {
+        0.0f64.to_bits() // -0.0 too
+    } else {
+        v.to_bits()
+    }
+}
+
+/// UTF-8 byte order mark
+const UTF8BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Deepest nesting of maps and arrays the parsers accept by default.
+/// Real messages are a few levels deep.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Input with maps and arrays nested deeper than the parser's limit.
+/// Returned inside an anyhow::Error; use downcast_ref to identify it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NestingTooDeep {
+    pub limit: usize,    // most open maps and arrays allowed
+    pub position: usize, // of the map or array which went past the limit
+}
+
+impl std::fmt::Display for NestingTooDeep {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "LLSD nested more than {} deep at position {}",
+            self.limit, self.position
+        )
+    }
+}
+
+impl std::error::Error for NestingTooDeep {}
+
+/// Input longer than `ParseOptions::max_size`. Nothing was parsed.
+/// Returned inside an anyhow::Error; use downcast_ref to identify it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputTooLarge {
+    pub limit: usize, // most bytes allowed
+    pub size: usize,  // bytes offered
+}
+
+impl std::fmt::Display for InputTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "LLSD input of {} bytes is over the limit of {}",
+            self.size, self.limit
+        )
+    }
+}
+
+impl std::error::Error for InputTooLarge {}
+
+/// Options for parsing. The default follows the LLSD spec exactly, with
+/// a nesting limit of `DEFAULT_MAX_DEPTH` and no size limit.
+/// See also `ParseOptions::strict` and `ParseOptions::lenient`.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Accept some common spellings which are not valid LLSD, such as
+    /// hexadecimal integers and reals with a decimal comma in XML, and
+    /// map keys without the 'k' prefix in binary. `LLSDValue::parse_with_options`
+    /// also accepts XML which is not valid UTF-8, replacing the bad bytes.
+    /// Off by default.
+    pub lenient: bool,
+    /// Reject strings, URIs, and map keys containing NUL characters. They are
+    /// valid UTF-8, but trouble for C consumers. Off by default. Binary format only.
+    pub reject_nul_in_strings: bool,
+    /// Most maps and arrays open one inside another. Deeper input is a
+    /// `NestingTooDeep` error. The XML and notation parsers recurse, so
+    /// a much larger limit than the default needs a bigger stack.
+    pub max_depth: usize,
+    /// Longest input accepted, in bytes. Longer input is an `InputTooLarge`
+    /// error, before anything is parsed. The parsed value can take several
+    /// times the input size in memory.
+    pub max_size: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            lenient: false,
+            reject_nul_in_strings: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_size: usize::MAX,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Everything the spec forbids is rejected, and every check is on,
+    /// with limits of 64 levels of nesting and 16 MB of input.
+    /// Recommended for data from untrusted sources.
+    pub fn strict() -> ParseOptions {
+        ParseOptions {
+            lenient: false,
+            reject_nul_in_strings: true,
+            max_depth: 64,
+            max_size: 16 * 1024 * 1024,
+        }
+    }
+
+    /// Accept all the relaxations, for talking to sloppy implementations.
+    /// The limits are the defaults.
+    pub fn lenient() -> ParseOptions {
+        ParseOptions {
+            lenient: true,
+            ..Default::default()
+        }
+    }
+
+    /// Error if input of `size` bytes is over `max_size`.
+    pub(crate) fn check_size(&self, size: usize) -> Result<(), Error> {
+        if size > self.max_size {
+            return Err(InputTooLarge {
+                limit: self.max_size,
+                size,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Error if `depth` maps and arrays open is over `max_depth`.
+    /// `position` is that of the one just opened.
+    pub(crate) fn check_depth(&self, depth: usize, position: usize) -> Result<(), Error> {
+        if depth > self.max_depth {
+            return Err(NestingTooDeep {
+                limit: self.max_depth,
+                position,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Something odd, but not fatal, found by `parse_with_diagnostics`
+/// or `xml::parse_collecting_errors`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    /// A map key which appeared more than once. The last value was kept,
+    /// as always. `path` is the JSON Pointer to the entry.
+    DuplicateKey { key: String, path: String },
+    /// A value whose text could not be converted, replaced by Undefined.
+    /// Only from `xml::parse_collecting_errors`. `message` is the parse error.
+    BadValue { path: String, message: String },
+}
+
+/// Collects diagnostics during a parse, if enabled, keeping track of the
+/// path to the value being parsed. When disabled, this costs almost nothing.
+pub(crate) struct Diagnostics {
+    enabled: bool,
+    recover: bool,     // bad values become Undefined instead of errors
+    path: Vec<String>, // escaped path segments
+    found: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub(crate) fn new(enabled: bool) -> Diagnostics {
+        Diagnostics {
+            enabled,
+            recover: false,
+            path: Vec::new(),
+            found: Vec::new(),
+        }
+    }
+
+    /// Enabled, and recovering from bad values where the parser can.
+    pub(crate) fn collecting_errors() -> Diagnostics {
+        Diagnostics {
+            recover: true,
+            ..Diagnostics::new(true)
+        }
+    }
+
+    /// JSON Pointer to the current value, with an optional last step.
+    fn current_path(&self, last: Option<&str>) -> String {
+        let mut path = String::new();
+        for step in self.path.iter().map(|s| s.as_str()).chain(last) {
+            path.push('/');
+            path.push_str(step);
+        }
+        path
+    }
+
+    /// A parsed value, or Undefined and a diagnostic if recovering.
+    pub(crate) fn recover(&mut self, result: Result<LLSDValue, Error>) -> Result<LLSDValue, Error> {
+        match result {
+            Err(e) if self.recover => {
+                self.found.push(Diagnostic::BadValue {
+                    path: self.current_path(None),
+                    message: e.to_string(),
+                });
+                Ok(LLSDValue::Undefined)
+            }
+            _ => result,
+        }
+    }
+
+    /// Entering the value for a map key.
+    pub(crate) fn push_key(&mut self, key: &str) {
+        if self.enabled {
+            self.path.push(escape_path_key(key));
+        }
+    }
+
+    /// Entering an array element.
+    pub(crate) fn push_index(&mut self, n: usize) {
+        if self.enabled {
+            self.path.push(n.to_string());
+        }
+    }
+
+    /// Leaving a map value or array element.
+    pub(crate) fn pop(&mut self) {
+        if self.enabled {
+            let _ = self.path.pop();
+        }
+    }
+
+    /// Insert into a map being parsed, noting duplicate keys.
+    pub(crate) fn insert(
+        &mut self,
+        map: &mut HashMap<String, LLSDValue>,
+        key: String,
+        value: LLSDValue,
+    ) {
+        use std::collections::hash_map::Entry;
+        match map.entry(key) {
+            Entry::Occupied(mut entry) => {
+                if self.enabled {
+                    let path = self.current_path(Some(&escape_path_key(entry.key())));
+                    self.found.push(Diagnostic::DuplicateKey {
+                        key: entry.key().clone(),
+                        path,
+                    });
+                }
+                let _ = entry.insert(value); // Duplicates are not errors, per LLSD spec.
+            }
+            Entry::Vacant(entry) => {
+                let _ = entry.insert(value);
+            }
+        }
+    }
+}
+
+/// The LLSD serialization formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Binary,
+    Xml,
+    Notation,
+}
+
+impl Format {
+    /// Format from an HTTP Content-Type or Accept header value, such as
+    /// "application/llsd+xml; charset=utf-8". For a list, as in Accept,
+    /// the first LLSD type listed wins. None if no LLSD type is present.
+    pub fn from_content_type(s: &str) -> Option<Format> {
+        s.split(',')
+            .map(|item| {
+                item.split(';')
+                    .next()
+                    .unwrap_or("")
+                    .trim()
+                    .to_ascii_lowercase()
+            })
+            .find_map(|mimetype| match mimetype.as_str() {
+                "application/llsd+xml" => Some(Format::Xml),
+                "application/llsd+binary" => Some(Format::Binary),
+                "application/llsd+notation" => Some(Format::Notation),
+                _ => None,
+            })
+    }
+
+    /// The MIME type for this format.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Xml => "application/llsd+xml",
+            Format::Binary => "application/llsd+binary",
+            Format::Notation => "application/llsd+notation",
+        }
+    }
+}
+
 //  Implementation
 
 impl LLSDValue {
     /// Parse LLSD, detecting format.
+    /// A UTF-8 byte order mark and whitespace before the data are ignored.
+    /// Binary must otherwise start with its header, or with the '{' or '['
+    /// of a headerless map or array.
     pub fn parse(msg: &[u8]) -> Result<LLSDValue, Error> {
-        //  Try binary first
-        if msg.len() >= binary::LLSDBINARYSENTINEL.len()
-            && &msg[0..binary::LLSDBINARYSENTINEL.len()] == binary::LLSDBINARYSENTINEL
-        {
-            return binary::parse_array(&msg[binary::LLSDBINARYSENTINEL.len()..]);
+        Ok(LLSDValue::parse_with_format(msg)?.0)
+    }
+
+    /// Parse LLSD from a reader, such as a file or socket, detecting format.
+    /// Binary with a header is parsed as it is read. Other formats are read
+    /// to end of data first.
+    pub fn from_reader<R: Read>(mut r: R) -> Result<LLSDValue, Error> {
+        //  Read enough to recognize the binary header.
+        let mut msg = vec![0u8; binary::LLSDBINARYSENTINEL.len()];
+        let mut len = 0;
+        while len < msg.len() {
+            match r.read(&mut msg[len..])? {
+                0 => break, // end of data
+                n => len += n,
+            }
+        }
+        msg.truncate(len);
+        if msg == binary::LLSDBINARYSENTINEL {
+            return binary::parse_read(&mut r);
+        }
+        r.read_to_end(&mut msg)?;
+        LLSDValue::parse(&msg)
+    }
+
+    /// Parse LLSD, detecting format, and report which format it was.
+    pub fn parse_with_format(msg: &[u8]) -> Result<(LLSDValue, Format), Error> {
+        LLSDValue::parse_detecting(msg, &ParseOptions::default(), &mut Diagnostics::new(false))
+    }
+
+    /// Parse LLSD, detecting format, with options. Notation uses only the limits.
+    pub fn parse_with_options(msg: &[u8], options: &ParseOptions) -> Result<LLSDValue, Error> {
+        let (val, _) = LLSDValue::parse_detecting(msg, options, &mut Diagnostics::new(false))?;
+        Ok(val)
+    }
+
+    /// Parse an HTTP body, using the Content-Type header value to pick the parser,
+    /// since sniffing can guess wrong. The binary header is optional.
+    /// Types other than the LLSD MIME types fall back to detecting the format.
+    pub fn parse_with_content_type(body: &[u8], content_type: &str) -> Result<LLSDValue, Error> {
+        match Format::from_content_type(content_type) {
+            Some(Format::Binary) => binary::parse_array(
+                body.strip_prefix(binary::LLSDBINARYSENTINEL)
+                    .unwrap_or(body),
+            ),
+            Some(Format::Xml) => xml::parse(std::str::from_utf8(
+                body.strip_prefix(UTF8BOM).unwrap_or(body),
+            )?),
+            Some(Format::Notation) => notation::parse(body.strip_prefix(UTF8BOM).unwrap_or(body)),
+            None => LLSDValue::parse(body),
+        }
+    }
+
+    /// Parse LLSD, detecting format, and also report oddities which the
+    /// parse accepts, such as duplicate map keys. For debugging malformed input.
+    pub fn parse_with_diagnostics(msg: &[u8]) -> Result<(LLSDValue, Vec<Diagnostic>), Error> {
+        let mut diags = Diagnostics::new(true);
+        let (val, _) = LLSDValue::parse_detecting(msg, &ParseOptions::default(), &mut diags)?;
+        Ok((val, diags.found))
+    }
+
+    /// Parse LLSD, detecting format.
+    fn parse_detecting(
+        msg: &[u8],
+        options: &ParseOptions,
+        diags: &mut Diagnostics,
+    ) -> Result<(LLSDValue, Format), Error> {
+        //  Some editors put a UTF-8 byte order mark at the start of text files.
+        let msg = msg.strip_prefix(UTF8BOM).unwrap_or(msg);
+        let trimmed = &msg[msg.iter().take_while(|ch| ch.is_ascii_whitespace()).count()..];
+        //  Try binary first. Stray whitespace before the header is allowed,
+        //  but nothing else.
+        if let Some(body) = trimmed.strip_prefix(binary::LLSDBINARYSENTINEL) {
+            return Ok((
+                binary::parse_diagnosed(body, options, diags)?,
+                Format::Binary,
+            ));
         }
         //  Check for binary without header. If array or map marker, parse.
+        //  Notation starts the same way, but binary has a 4-byte count next,
+        //  and its high byte is zero for any plausible count.
         if msg.len() > 1 {
             match msg[0] {
                 // check first char
-                b'{' | b'[' => return binary::parse_array(msg),
+                b'{' | b'[' if msg[1] == 0 => {
+                    return Ok((
+                        binary::parse_diagnosed(msg, options, diags)?,
+                        Format::Binary,
+                    ))
+                }
                 _ => {}
             }
         }
+        //  Notation may contain raw binary, so check for it before UTF-8 conversion.
+        if trimmed.starts_with(notation::LLSDNOTATIONSENTINEL) {
+            return Ok((
+                notation::parse_diagnosed(msg, options, diags)?,
+                Format::Notation,
+            ));
+        }
         //  No binary sentinel, try text format.
-        let msgstring = std::str::from_utf8(msg)?; // convert to UTF-8 string
-        if msgstring.trim_start().starts_with(xml::LLSDXMLSENTINEL) {
-            // try XML
-            return xml::parse(msgstring);
-        }
-        //  "Notation" syntax is not currently supported.
-        //  Trim sring to N chars for error msg.
-        let snippet = msgstring
+        if trimmed.starts_with(xml::LLSDXMLSENTINEL.as_bytes()) {
+            //  Lenient mode replaces bad UTF-8, such as stray Latin-1, with U+FFFD.
+            let xmlstring = match std::str::from_utf8(msg) {
+                Ok(s) => Cow::Borrowed(s),
+                Err(_) if options.lenient => String::from_utf8_lossy(msg),
+                Err(e) => return Err(llsd_error!(Encoding, "XML LLSD, invalid UTF-8: {}", e)),
+            };
+            return Ok((
+                xml::parse_diagnosed(&xmlstring, options, diags)?,
+                Format::Xml,
+            ));
+        }
+        //  Notation without a header. Check that it starts with a notation type code.
+        //  Raw binary inside may not be UTF-8, so this is before any conversion.
+        if let Some(
+            b'!' | b'0' | b'1' | b't' | b'T' | b'f' | b'F' | b'i' | b'r' | b'u' | b'\'' | b'"'
+            | b's' | b'l' | b'd' | b'b' | b'{' | b'[',
+        ) = trimmed.first()
+        {
+            return Ok((
+                notation::parse_diagnosed(msg, options, diags)?,
+                Format::Notation,
+            ));
+        }
+        //  Trim string to N chars for error msg.
+        let snippet = String::from_utf8_lossy(msg)
             .chars()
             .zip(0..60)
             .map(|(c, _)| c)
             .collect::<String>();
         Err(anyhow!("LLSD format not recognized: {:?}", snippet))
     }
+
+    /// Flatten a tree into (path, scalar) pairs, for logging and metrics.
+    ///
+    /// Map keys are joined with `.` and array indices appear as `[N]`,
+    /// so a value can come out as `a.b[0].c`. Only scalar leaves are returned;
+    /// empty maps and arrays produce nothing. Any `.`, `[`, `]` or `\`
+    /// inside a key is escaped with a backslash, so paths are unambiguous.
+    /// A scalar at the top level has the empty path.
+    pub fn flatten(&self) -> Vec<(String, &LLSDValue)> {
+        fn escape_key(key: &str) -> String {
+            let mut s = String::with_capacity(key.len());
+            for ch in key.chars() {
+                if matches!(ch, '.' | '[' | ']' | '\\') {
+                    s.push('\\');
+                }
+                s.push(ch);
+            }
+            s
+        }
+        fn walk<'a>(val: &'a LLSDValue, path: String, out: &mut Vec<(String, &'a LLSDValue)>) {
+            match val {
+                LLSDValue::Map(m) => {
+                    for (key, value) in m {
+                        let child = if path.is_empty() {
+                            escape_key(key)
+                        } else {
+                            format!("{}.{}", path, escape_key(key))
+                        };
+                        walk(value, child, out);
+                    }
+                }
+                LLSDValue::Array(a) => {
+                    for (n, value) in a.iter().enumerate() {
+                        walk(value, format!("{}[{}]", path, n), out);
+                    }
+                }
+                _ => out.push((path, val)),
+            }
+        }
+        let mut out = Vec::new();
+        walk(self, String::new(), &mut out);
+        out
+    }
+
+    /// True if this is a map containing the key. False for non-maps.
+    pub fn contains_key(&self, key: &str) -> bool {
+        match self {
+            LLSDValue::Map(m) => m.contains_key(key),
+            _ => false,
+        }
+    }
+
+    /// Look up a value by path, such as "/region/stats/0".
+    ///
+    /// Paths use JSON Pointer syntax: each segment starts with `/`, and is a map
+    /// key or an array index. Within a key, `~1` stands for `/` and `~0` for `~`.
+    /// The empty path is the value itself.
+    pub fn pointer(&self, path: &str) -> Option<&LLSDValue> {
+        let mut target = self;
+        for key in split_path(path).ok()? {
+            target = match target {
+                LLSDValue::Map(m) => m.get(&key)?,
+                LLSDValue::Array(a) => a.get(key.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(target)
+    }
+
+    /// Look up a value by path, for modification in place. See `pointer`.
+    pub fn pointer_mut(&mut self, path: &str) -> Option<&mut LLSDValue> {
+        let mut target = self;
+        for key in split_path(path).ok()? {
+            target = match target {
+                LLSDValue::Map(m) => m.get_mut(&key)?,
+                LLSDValue::Array(a) => a.get_mut(key.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(target)
+    }
+
+    /// Remove the value at a path and return it. A map entry is removed;
+    /// an array element is removed and later elements move down.
+    /// The empty path takes the whole value, leaving Undefined.
+    /// None if there is nothing at the path. See `pointer` for path syntax.
+    pub fn take_path(&mut self, path: &str) -> Option<LLSDValue> {
+        let mut keys = split_path(path).ok()?;
+        let last = match keys.pop() {
+            Some(last) => last,
+            None => return Some(std::mem::replace(self, LLSDValue::Undefined)),
+        };
+        let mut target = self;
+        for key in keys {
+            target = match target {
+                LLSDValue::Map(m) => m.get_mut(&key)?,
+                LLSDValue::Array(a) => a.get_mut(key.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        match target {
+            LLSDValue::Map(m) => m.remove(&last),
+            LLSDValue::Array(a) => match last.parse::<usize>() {
+                Ok(n) if n < a.len() => Some(a.remove(n)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Store a value at a path, creating maps along the way as needed.
+    /// See `pointer` for path syntax.
+    ///
+    /// A missing or Undefined step becomes a new map. Array steps must
+    /// be existing indices. It is an error for a step to be some other
+    /// scalar, such as a string; that is never overwritten.
+    pub fn set_path(&mut self, path: &str, value: LLSDValue) -> Result<(), Error> {
+        self.set_path_or_return(path, value).map_err(|(e, _)| e)
+    }
+
+    /// As `set_path`, but on failure the value is handed back, unchanged.
+    /// Nothing is created along the way unless the store succeeds.
+    pub(crate) fn set_path_or_return(
+        &mut self,
+        path: &str,
+        value: LLSDValue,
+    ) -> Result<(), (Error, LLSDValue)> {
+        let keys = match split_path(path) {
+            Ok(keys) => keys,
+            Err(e) => return Err((e, value)),
+        };
+        let mut target = self;
+        for key in keys {
+            if let LLSDValue::Undefined = target {
+                *target = LLSDValue::Map(HashMap::new());
+            }
+            target = match target {
+                LLSDValue::Map(m) => m.entry(key).or_insert(LLSDValue::Undefined),
+                LLSDValue::Array(a) => match key.parse::<usize>() {
+                    Ok(n) if n < a.len() => &mut a[n],
+                    _ => {
+                        return Err((
+                            anyhow!("LLSD path {:?}: no array index {:?}", path, key),
+                            value,
+                        ))
+                    }
+                },
+                _ => {
+                    return Err((
+                        anyhow!(
+                            "LLSD path {:?}: cannot look up {:?} in a scalar value",
+                            path,
+                            key
+                        ),
+                        value,
+                    ))
+                }
+            };
+        }
+        *target = value;
+        Ok(())
+    }
+
+    /// Boolean value, from whichever form an emitter used. Integers and reals
+    /// are true if nonzero, and strings are read with `parse_bool_text`.
+    /// None for NaN, unrecognized strings, and other types.
+    pub fn as_bool_coerced(&self) -> Option<bool> {
+        match self {
+            LLSDValue::Boolean(v) => Some(*v),
+            LLSDValue::Integer(v) => Some(*v != 0),
+            LLSDValue::Real(v) if v.is_nan() => None,
+            LLSDValue::Real(v) => Some(*v != 0.0),
+            LLSDValue::String(v) => parse_bool_text(v).ok(),
+            _ => None,
+        }
+    }
+
+    /// Numeric value as f64. Integers are widened. None for non-numbers.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            LLSDValue::Real(v) => Some(*v),
+            LLSDValue::Integer(v) => Some(f64::from(*v)),
+            _ => None,
+        }
+    }
+
+    /// Numeric value as i64. Reals are accepted only if they are whole
+    /// numbers in range; fractional reals give None rather than being rounded.
+    /// None for non-numbers.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            LLSDValue::Integer(v) => Some(i64::from(*v)),
+            //  i64::MAX is not exact as an f64, so test against 2^63.
+            LLSDValue::Real(v)
+                if v.fract() == 0.0 && *v >= -(2f64.powi(63)) && *v < 2f64.powi(63) =>
+            {
+                Some(*v as i64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Numeric value as u32, for emitters which put values beyond the i32 range
+    /// into reals. Same rules as `as_i64`, and negative values give None.
+    pub fn as_u32(&self) -> Option<u32> {
+        match self.as_i64() {
+            Some(v) => u32::try_from(v).ok(),
+            None => None,
+        }
+    }
+
+    /// Append to an Array. Error if not an array.
+    pub fn array_push(&mut self, value: impl Into<LLSDValue>) -> Result<(), Error> {
+        match self {
+            LLSDValue::Array(a) => {
+                a.push(value.into());
+                Ok(())
+            }
+            _ => Err(anyhow!(
+                "LLSD array_push on a value which is not an array: {:?}",
+                self
+            )),
+        }
+    }
+
+    /// Insert into a Map, returning any previous value for the key.
+    /// Error if not a map.
+    pub fn map_insert(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<LLSDValue>,
+    ) -> Result<Option<LLSDValue>, Error> {
+        match self {
+            LLSDValue::Map(m) => Ok(m.insert(key.into(), value.into())),
+            _ => Err(anyhow!(
+                "LLSD map_insert on a value which is not a map: {:?}",
+                self
+            )),
+        }
+    }
+
+    /// Mutable reference to the value for `key` in a Map, inserting Undefined
+    /// if absent, like HashMap::entry. An Undefined value becomes an empty Map first,
+    /// so nested maps can be built with `*v.entry("a").entry("b") = 5.into()`.
+    ///
+    /// Panics if the value is neither a Map nor Undefined. Use map_insert to get an error instead.
+    pub fn entry(&mut self, key: &str) -> &mut LLSDValue {
+        if let LLSDValue::Undefined = self {
+            *self = LLSDValue::Map(HashMap::new());
+        }
+        match self {
+            LLSDValue::Map(m) => m.entry(key.to_string()).or_insert(LLSDValue::Undefined),
+            _ => panic!(
+                "LLSD entry({:?}) on a value which is not a map: {:?}",
+                key, self
+            ),
+        }
+    }
+
+    /// Keep only the children for which `f` returns true, in place.
+    /// For a map, `f` gets each key and value; for an array, None and each element.
+    /// Scalars are unchanged.
+    pub fn retain<F: FnMut(Option<&str>, &LLSDValue) -> bool>(&mut self, mut f: F) {
+        match self {
+            LLSDValue::Map(m) => m.retain(|key, value| f(Some(key), value)),
+            LLSDValue::Array(a) => a.retain(|value| f(None, value)),
+            _ => {}
+        }
+    }
+
+    /// Change String values to URI for map entries with any of the given keys,
+    /// at any depth. For data from sources which have no URI type, such as JSON,
+    /// where the schema says which fields are URIs.
+    pub fn normalize_uris(&mut self, keys: &[&str]) {
+        match self {
+            LLSDValue::Map(m) => {
+                for (key, value) in m.iter_mut() {
+                    if let LLSDValue::String(s) = value {
+                        if keys.contains(&key.as_str()) {
+                            *value = LLSDValue::URI(std::mem::take(s));
+                        }
+                    } else {
+                        value.normalize_uris(keys);
+                    }
+                }
+            }
+            LLSDValue::Array(a) => a.iter_mut().for_each(|value| value.normalize_uris(keys)),
+            _ => {}
+        }
+    }
+
+    /// Cut strings, URIs, and map keys to at most `max_chars` characters,
+    /// at any depth. Counts Unicode scalar values, so no character is split.
+    /// Map keys which become the same after cutting collapse to one entry,
+    /// the one whose original key sorts first.
+    pub fn truncate_strings(&mut self, max_chars: usize) {
+        fn truncate(s: &mut String, max_chars: usize) {
+            if let Some((pos, _)) = s.char_indices().nth(max_chars) {
+                s.truncate(pos);
+            }
+        }
+        match self {
+            LLSDValue::String(s) | LLSDValue::URI(s) => truncate(s, max_chars),
+            LLSDValue::Map(m) => {
+                let mut entries: Vec<(String, LLSDValue)> = m.drain().collect();
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                for (mut key, mut value) in entries {
+                    truncate(&mut key, max_chars);
+                    value.truncate_strings(max_chars);
+                    m.entry(key).or_insert(value);
+                }
+            }
+            LLSDValue::Array(a) => a
+                .iter_mut()
+                .for_each(|value| value.truncate_strings(max_chars)),
+            _ => {}
+        }
+    }
+
+    /// Check that the value can be written in the given format, without writing it.
+    /// The error reports the first problem found, with its path. Binary cannot
+    /// hold strings, blobs, maps or arrays with more than 2^32-1 bytes or entries.
+    /// XML cannot hold most control characters. XML and notation cannot write
+    /// dates outside years 0000 to 9999. Non-finite reals are fine in all three;
+    /// for JSON, which cannot hold them, see `json::NonFinitePolicy`.
+    pub fn check_serializable(&self, format: Format) -> Result<(), Error> {
+        self.check_serializable_at(format, "", u32::MAX as usize)
+    }
+
+    /// Check a value, at path. Uses a work list rather than recursing,
+    /// so that any depth can be checked.
+    fn check_serializable_at(
+        &self,
+        format: Format,
+        path: &str,
+        max_len: usize,
+    ) -> Result<(), Error> {
+        let mut work: Vec<(&LLSDValue, String)> = vec![(self, path.to_string())];
+        while let Some((val, path)) = work.pop() {
+            let fail = |problem: String| {
+                Err(llsd_error!(
+                    Value,
+                    "LLSD value at {:?} cannot be written as {:?}: {}",
+                    path,
+                    format,
+                    problem
+                ))
+            };
+            let check_len = |what: &str, len: usize| {
+                if format == Format::Binary && len > max_len {
+                    fail(format!("{} length {} exceeds {}", what, len, max_len))
+                } else {
+                    Ok(())
+                }
+            };
+            let check_text = |what: &str, text: &str| {
+                check_len(what, text.len())?;
+                if format == Format::Xml {
+                    if let Err(e) = xml::xml_escape(text) {
+                        return fail(e.to_string());
+                    }
+                }
+                Ok(())
+            };
+            match val {
+                LLSDValue::String(v) => check_text("string", v)?,
+                LLSDValue::URI(v) => check_text("URI", v)?,
+                LLSDValue::Binary(v) => check_len("binary", v.len())?,
+                LLSDValue::Date(v) if format != Format::Binary && !xml::date_in_range(*v) => {
+                    fail(format!("date {} out of range", v))?
+                }
+                LLSDValue::Map(m) => {
+                    check_len("map", m.len())?;
+                    for (key, value) in m {
+                        check_text("map key", key)?;
+                        work.push((value, format!("{}/{}", path, escape_path_key(key))));
+                    }
+                }
+                LLSDValue::Array(a) => {
+                    check_len("array", a.len())?;
+                    //  Reversed, so that elements are checked in order.
+                    for (n, value) in a.iter().enumerate().rev() {
+                        work.push((value, format!("{}/{}", path, n)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Equality, except that a map entry whose value is Undefined matches
+    /// a missing entry, at any depth. Arrays must be the same length,
+    /// with elements compared the same way; an Undefined element does
+    /// not match a missing one, since that would shift the rest.
+    /// Otherwise as `==`.
+    pub fn eq_ignoring_undefined(&self, other: &LLSDValue) -> bool {
+        match (self, other) {
+            (LLSDValue::Map(a), LLSDValue::Map(b)) => {
+                let matches = |x: &HashMap<String, LLSDValue>, y: &HashMap<String, LLSDValue>| {
+                    x.iter().all(|(key, xval)| match y.get(key) {
+                        Some(yval) => xval.eq_ignoring_undefined(yval),
+                        None => *xval == LLSDValue::Undefined,
+                    })
+                };
+                matches(a, b) && matches(b, a)
+            }
+            (LLSDValue::Array(a), LLSDValue::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.eq_ignoring_undefined(y))
+            }
+            _ => self == other,
+        }
+    }
+
+    /// Equality, except that an Integer equals a Real with the same numeric value,
+    /// at any depth. For data from writers which pick integer or real inconsistently.
+    /// Unlike `==`, `Integer(5)` and `Real(5.0)` match. Otherwise as `==`.
+    pub fn numeric_eq(&self, other: &LLSDValue) -> bool {
+        match (self, other) {
+            (LLSDValue::Integer(i), LLSDValue::Real(r))
+            | (LLSDValue::Real(r), LLSDValue::Integer(i)) => f64::from(*i) == *r,
+            (LLSDValue::Map(a), LLSDValue::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(key, aval)| b.get(key).is_some_and(|bval| aval.numeric_eq(bval)))
+            }
+            (LLSDValue::Array(a), LLSDValue::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.numeric_eq(y))
+            }
+            _ => self == other,
+        }
+    }
+
+    /// An array of exactly N reals, such as a vector or quaternion, as a Rust array.
+    /// None if not an array, the wrong length, or any element is not a Real.
+    pub fn as_real_array<const N: usize>(&self) -> Option<[f64; N]> {
+        let items = self.as_array()?;
+        if items.len() != N {
+            return None;
+        }
+        let mut out = [0.0; N];
+        for (v, item) in out.iter_mut().zip(items) {
+            *v = *item.as_real()?;
+        }
+        Some(out)
+    }
+
+    /// An array of exactly N integers, as a Rust array.
+    /// None if not an array, the wrong length, or any element is not an Integer.
+    pub fn as_integer_array<const N: usize>(&self) -> Option<[i32; N]> {
+        let items = self.as_array()?;
+        if items.len() != N {
+            return None;
+        }
+        let mut out = [0; N];
+        for (v, item) in out.iter_mut().zip(items) {
+            *v = *item.as_integer()?;
+        }
+        Some(out)
+    }
+
+    /// Keys of a map, in no particular order. Empty for non-maps.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.as_map()
+            .into_iter()
+            .flat_map(|m| m.keys().map(|k| k.as_str()))
+    }
+
+    /// True for everything except maps and arrays.
+    pub fn is_scalar(&self) -> bool {
+        !self.is_container()
+    }
+
+    /// True for maps and arrays.
+    pub fn is_container(&self) -> bool {
+        matches!(self, LLSDValue::Map(_) | LLSDValue::Array(_))
+    }
+
+    /// Keys of a map, sorted, for reproducible output. Empty for non-maps.
+    pub fn sorted_keys(&self) -> Vec<&str> {
+        self.sorted_entries().into_iter().map(|(k, _)| k).collect()
+    }
+
+    /// Entries of a map, sorted by key, for reproducible output. Empty for non-maps.
+    pub fn sorted_entries(&self) -> Vec<(&str, &LLSDValue)> {
+        match self {
+            LLSDValue::Map(m) => sorted_map_entries(m)
+                .into_iter()
+                .map(|(k, v)| (k.as_str(), v))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Elements of an array. Empty for non-arrays.
+    pub fn array_elements(&self) -> &[LLSDValue] {
+        match self {
+            LLSDValue::Array(a) => a,
+            _ => &[],
+        }
+    }
+
+    /// Entries of a map, in no particular order. Empty for non-maps.
+    pub fn map_entries(&self) -> impl Iterator<Item = (&str, &LLSDValue)> {
+        self.as_map()
+            .into_iter()
+            .flat_map(|m| m.iter().map(|(k, v)| (k.as_str(), v)))
+    }
+
+    /// A Date as whole milliseconds since the Unix epoch, as JavaScript uses.
+    /// Rounds to the nearest millisecond. None if not a Date, or not finite,
+    /// or out of range for an i64.
+    pub fn as_unix_millis(&self) -> Option<i64> {
+        let ms = (self.as_date()? * 1000.0).round();
+        if ms.is_finite() && ms >= i64::MIN as f64 && ms < i64::MAX as f64 {
+            Some(ms as i64)
+        } else {
+            None
+        }
+    }
+
+    /// A Date from milliseconds since the Unix epoch.
+    pub fn from_unix_millis(ms: i64) -> LLSDValue {
+        LLSDValue::Date(ms as f64 / 1000.0)
+    }
+
+    /// Number of nodes in the tree, counting scalars and containers.
+    /// A scalar is 1; an empty map or array is 1.
+    pub fn count_nodes(&self) -> usize {
+        1 + match self {
+            LLSDValue::Map(m) => m.values().map(|v| v.count_nodes()).sum(),
+            LLSDValue::Array(a) => a.iter().map(|v| v.count_nodes()).sum(),
+            _ => 0,
+        }
+    }
+
+    /// Nesting depth of the tree. A scalar or empty container is 1,
+    /// an array of scalars is 2, and so on.
+    pub fn max_depth(&self) -> usize {
+        1 + match self {
+            LLSDValue::Map(m) => m.values().map(|v| v.max_depth()).max().unwrap_or(0),
+            LLSDValue::Array(a) => a.iter().map(|v| v.max_depth()).max().unwrap_or(0),
+            _ => 0,
+        }
+    }
+}
+
+/// Report a parse error as a trace event, with feature "tracing".
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn trace_error<T>(format: Format, result: Result<T, Error>) -> Result<T, Error> {
+    #[cfg(feature = "tracing")]
+    if let Err(e) = &result {
+        tracing::trace!(?format, error = %e, "LLSD parse error");
+    }
+    result
+}
+
+/// Broad category of an error from this crate, from `classify_error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LLSDErrorKind {
+    /// The input ended before the value was complete.
+    Truncated,
+    /// Bytes which are not valid UTF-8, base 64, base 16, or base 85.
+    Encoding,
+    /// A well-formed item with a bad value, such as an integer too big
+    /// for 32 bits, a malformed UUID, or a date out of range.
+    Value,
+    /// Anything else wrong with the structure: unknown type codes or tags,
+    /// missing delimiters, unwanted data after the value.
+    Syntax,
+    /// An I/O error other than end of data, from a reader or writer.
+    Io,
+    /// Input over a limit in `ParseOptions`, such as nesting depth.
+    Limit,
+}
+
+/// An error from this crate which has no more specific type, with its kind.
+/// Returned inside an anyhow::Error; use downcast_ref to get at the kind,
+/// or just call `classify_error`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LLSDError {
+    pub kind: LLSDErrorKind,
+    pub message: String,
+}
+
+impl std::fmt::Display for LLSDError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for LLSDError {}
+
+/// Classify an error returned by this crate, so callers can branch on
+/// the kind of problem without looking at the message text.
+/// Errors of other types from elsewhere are classified as well as possible,
+/// and anything unknown is `Syntax`.
+pub fn classify_error(e: &Error) -> LLSDErrorKind {
+    //  The outermost typed error in the chain decides.
+    for cause in e.chain() {
+        if let Some(err) = cause.downcast_ref::<LLSDError>() {
+            return err.kind;
+        }
+        if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+            return if io.kind() == std::io::ErrorKind::UnexpectedEof {
+                LLSDErrorKind::Truncated
+            } else {
+                LLSDErrorKind::Io
+            };
+        }
+        if cause.is::<std::str::Utf8Error>()
+            || cause.is::<std::string::FromUtf8Error>()
+            || cause.is::<base64::DecodeError>()
+            || cause.is::<hex::FromHexError>()
+        {
+            return LLSDErrorKind::Encoding;
+        }
+        if cause.is::<xml::InvalidDate>()
+            || cause.is::<xml::UnrepresentableInXml>()
+            || cause.is::<uuid::Error>()
+            || cause.is::<chrono::ParseError>()
+            || cause.is::<std::num::ParseIntError>()
+            || cause.is::<std::num::ParseFloatError>()
+            || cause.is::<std::num::TryFromIntError>()
+        {
+            return LLSDErrorKind::Value;
+        }
+        if cause.is::<NestingTooDeep>() || cause.is::<InputTooLarge>() {
+            return LLSDErrorKind::Limit;
+        }
+        if cause.is::<notation::BinaryLengthMismatch>() || cause.is::<xml::MultipleRootValues>() {
+            return LLSDErrorKind::Syntax;
+        }
+    }
+    LLSDErrorKind::Syntax
+}
+
+/// Interpret the text of a boolean, as used by all the text formats.
+/// Accepts true, false, t, f, 1, and 0 in any case, with surrounding whitespace.
+/// Also 1.0 and 0.0, which LSL generates.
+pub fn parse_bool_text(s: &str) -> Result<bool, Error> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "true" | "t" | "1" | "1.0" => Ok(true),
+        "false" | "f" | "0" | "0.0" => Ok(false),
+        _ => Err(llsd_error!(Value, "LLSD boolean not recognized: {:?}", s)),
+    }
+}
+
+/// Split a JSON Pointer style path into its unescaped segments.
+fn split_path(path: &str) -> Result<Vec<String>, Error> {
+    if path.is_empty() {
+        return Ok(Vec::new()); // the whole value
+    }
+    if !path.starts_with('/') {
+        return Err(anyhow!("LLSD path must start with '/': {:?}", path));
+    }
+    Ok(path[1..]
+        .split('/')
+        .map(|key| key.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+/// Escape a map key for use as one segment of a path. Inverse of `split_path`.
+pub(crate) fn escape_path_key(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Map entries in key order. Serializers use this so that output is reproducible.
+pub(crate) fn sorted_map_entries(m: &HashMap<String, LLSDValue>) -> Vec<(&String, &LLSDValue)> {
+    let mut entries: Vec<(&String, &LLSDValue)> = m.iter().collect();
+    entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    entries
 }
 
 //  Unit tests
@@ -105,7 +1259,16 @@ fn testllsdvalue() {
     //  Extract some fields using enum_as_inner mechanism.
     ////assert_eq!(999, *test1map.as_map().unwrap().get("val2").unwrap().as_integer().unwrap());
     assert_eq!(42, *test1.as_array().unwrap()[1].as_integer().unwrap());
-    assert_eq!(999, *test1.as_array().unwrap()[2].as_map().unwrap().get("val2").unwrap().as_integer().unwrap());
+    assert_eq!(
+        999,
+        *test1.as_array().unwrap()[2]
+            .as_map()
+            .unwrap()
+            .get("val2")
+            .unwrap()
+            .as_integer()
+            .unwrap()
+    );
     //  Test error cases
     match LLSDValue::parse(
         b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<llsd><complex>2i</complex></llsd>",
@@ -118,3 +1281,1072 @@ fn testllsdvalue() {
         Ok(val) => panic!("Bad input not detected: {:?}", val)
     }
 }
+
+#[test]
+fn flattentest() {
+    //  Flatten part of a simulator statistics message.
+    const TESTXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+  <key>scale</key>
+    <string>one minute</string>
+  <key>simulator statistics</key>
+  <map>
+    <key>time dilation</key><real>0.9878624</real>
+    <key>sim fps</key><real>44.38898</real>
+    <key>array</key>
+        <array>
+            <boolean>false</boolean>
+            <integer>42</integer>
+        </array>
+    <key>a.b</key><integer>1</integer>
+  </map>
+</map>
+</llsd>
+"#;
+    let val = LLSDValue::parse(TESTXML.as_bytes()).unwrap();
+    let flat: HashMap<String, &LLSDValue> = val.flatten().into_iter().collect();
+    println!("Flattened: {:?}", flat);
+    assert_eq!(flat.len(), 6);
+    assert_eq!(
+        Some(&&LLSDValue::Real(44.38898)),
+        flat.get("simulator statistics.sim fps")
+    );
+    assert_eq!(
+        Some(&&LLSDValue::Integer(42)),
+        flat.get("simulator statistics.array[1]")
+    );
+    assert_eq!(
+        Some(&&LLSDValue::Integer(1)),
+        flat.get("simulator statistics.a\\.b")
+    );
+    //  A scalar at top level has the empty path.
+    assert_eq!(
+        vec![(String::new(), &LLSDValue::Integer(1))],
+        LLSDValue::Integer(1).flatten()
+    );
+}
+
+#[test]
+fn keystest() {
+    let map: LLSDValue = LLSDValue::Map(
+        [
+            ("val1".to_string(), LLSDValue::Real(456.0)),
+            ("val2".to_string(), LLSDValue::Integer(999)),
+        ]
+        .iter()
+        .cloned()
+        .collect(),
+    );
+    assert!(map.contains_key("val1"));
+    assert!(!map.contains_key("val3"));
+    let mut keys: Vec<&str> = map.keys().collect();
+    keys.sort();
+    assert_eq!(vec!["val1", "val2"], keys);
+    //  Non-maps have no keys.
+    let array = LLSDValue::Array(vec![LLSDValue::String("val1".to_string())]);
+    assert!(!array.contains_key("val1"));
+    assert_eq!(0, array.keys().count());
+    assert_eq!(0, LLSDValue::Undefined.keys().count());
+}
+
+#[test]
+fn pathtest() {
+    const TESTXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+  <key>scale</key>
+    <string>one minute</string>
+  <key>simulator statistics</key>
+  <map>
+    <key>sim fps</key><real>44.38898</real>
+    <key>array</key>
+        <array>
+            <boolean>false</boolean>
+            <integer>42</integer>
+        </array>
+  </map>
+</map>
+</llsd>
+"#;
+    let mut val = LLSDValue::parse(TESTXML.as_bytes()).unwrap();
+    assert_eq!(
+        Some(&LLSDValue::Integer(42)),
+        val.pointer("/simulator statistics/array/1")
+    );
+    assert_eq!(None, val.pointer("/simulator statistics/array/2"));
+    assert_eq!(None, val.pointer("/scale/x"));
+    assert_eq!(Some(&val), val.pointer(""));
+    //  Update in place.
+    *val.pointer_mut("/simulator statistics/sim fps").unwrap() = LLSDValue::Real(45.0);
+    assert_eq!(
+        Some(&LLSDValue::Real(45.0)),
+        val.pointer("/simulator statistics/sim fps")
+    );
+    val.set_path("/simulator statistics/array/0", LLSDValue::Boolean(true))
+        .unwrap();
+    assert_eq!(
+        Some(&LLSDValue::Boolean(true)),
+        val.pointer("/simulator statistics/array/0")
+    );
+    //  Create a new deep path.
+    val.set_path("/new/deep/a~1b", LLSDValue::Integer(7))
+        .unwrap();
+    assert_eq!(Some(&LLSDValue::Integer(7)), val.pointer("/new/deep/a~1b"));
+    assert!(val.pointer("/new/deep").unwrap().contains_key("a/b"));
+    //  Errors: scalar in the way, bad array index, bad path.
+    assert!(val.set_path("/scale/x", LLSDValue::Undefined).is_err());
+    assert_eq!(
+        Some(&LLSDValue::String("one minute".to_string())),
+        val.pointer("/scale")
+    );
+    assert!(val
+        .set_path("/simulator statistics/array/5", LLSDValue::Undefined)
+        .is_err());
+    assert!(val.set_path("scale", LLSDValue::Undefined).is_err());
+}
+
+#[test]
+fn fixedarraytest() {
+    let vector = LLSDValue::Array(vec![
+        LLSDValue::Real(1.0),
+        LLSDValue::Real(2.5),
+        LLSDValue::Real(-3.0),
+    ]);
+    assert_eq!(Some([1.0, 2.5, -3.0]), vector.as_real_array::<3>());
+    let [x, y, z] = vector.as_real_array().unwrap();
+    assert_eq!((1.0, 2.5, -3.0), (x, y, z));
+    //  Wrong length, wrong element type, not an array.
+    assert_eq!(None, vector.as_real_array::<4>());
+    assert_eq!(None, vector.as_integer_array::<3>());
+    assert_eq!(None, LLSDValue::Real(1.0).as_real_array::<1>());
+    let ints = LLSDValue::Array(vec![LLSDValue::Integer(4), LLSDValue::Integer(5)]);
+    assert_eq!(Some([4, 5]), ints.as_integer_array());
+    assert_eq!(None, ints.as_real_array::<2>()); // no silent conversion
+}
+
+#[test]
+fn formattest() {
+    let test1 = LLSDValue::Array(vec![
+        LLSDValue::Integer(42),
+        LLSDValue::String("x".to_string()),
+    ]);
+    let (val, format) = LLSDValue::parse_with_format(&binary::to_bytes(&test1).unwrap()).unwrap();
+    assert_eq!((&test1, Format::Binary), (&val, format));
+    let (val, format) =
+        LLSDValue::parse_with_format(xml::to_xml_string(&test1, true).unwrap().as_bytes()).unwrap();
+    assert_eq!((&test1, Format::Xml), (&val, format));
+    let (val, format) = LLSDValue::parse_with_format(&notation::to_bytes(&test1).unwrap()).unwrap();
+    assert_eq!((&test1, Format::Notation), (&val, format));
+    //  Notation without a header
+    let (val, format) = LLSDValue::parse_with_format(b"[i42,'x']").unwrap();
+    assert_eq!((&test1, Format::Notation), (&val, format));
+}
+
+#[test]
+fn bomtest() {
+    let test1 = LLSDValue::Array(vec![
+        LLSDValue::Integer(42),
+        LLSDValue::String("x".to_string()),
+    ]);
+    let mut bomxml = UTF8BOM.to_vec();
+    bomxml.extend_from_slice(xml::to_xml_string(&test1, true).unwrap().as_bytes());
+    let (val, format) = LLSDValue::parse_with_format(&bomxml).unwrap();
+    assert_eq!((&test1, Format::Xml), (&val, format));
+    let mut bomnotation = UTF8BOM.to_vec();
+    bomnotation.extend_from_slice(b"[i42,'x']");
+    let (val, format) = LLSDValue::parse_with_format(&bomnotation).unwrap();
+    assert_eq!((&test1, Format::Notation), (&val, format));
+}
+
+#[test]
+fn numericcoerciontest() {
+    assert_eq!(Some(5.0), LLSDValue::Integer(5).as_f64());
+    assert_eq!(Some(-2.5), LLSDValue::Real(-2.5).as_f64());
+    assert_eq!(None, LLSDValue::String("5".to_string()).as_f64());
+    assert_eq!(Some(5), LLSDValue::Integer(5).as_i64());
+    assert_eq!(Some(-7), LLSDValue::Real(-7.0).as_i64());
+    assert_eq!(None, LLSDValue::Real(7.5).as_i64()); // not rounded
+    assert_eq!(None, LLSDValue::Real(1.0e19).as_i64()); // out of range
+    assert_eq!(None, LLSDValue::Real(f64::NAN).as_i64());
+    assert_eq!(None, LLSDValue::Boolean(true).as_i64());
+}
+
+#[test]
+fn largeintegertest() {
+    //  Values past the i32 range, sent as reals.
+    const TESTXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<array><real>3000000000</real><real>3000000000.5</real><real>-1</real><integer>7</integer></array>
+</llsd>
+"#;
+    let parsed = LLSDValue::parse(TESTXML.as_bytes()).unwrap();
+    let items = parsed.as_array().unwrap();
+    assert_eq!(Some(3000000000), items[0].as_u32());
+    assert_eq!(Some(3000000000), items[0].as_i64());
+    assert_eq!(None, items[1].as_u32()); // fractional
+    assert_eq!(None, items[1].as_i64());
+    assert_eq!(None, items[2].as_u32()); // negative
+    assert_eq!(Some(-1), items[2].as_i64());
+    assert_eq!(Some(7), items[3].as_u32());
+    assert_eq!(None, LLSDValue::Real(5.0e9).as_u32()); // out of range
+}
+
+#[test]
+fn retaintest() {
+    let mut map = LLSDValue::Map(
+        [
+            ("a".to_string(), LLSDValue::Integer(1)),
+            (
+                "password".to_string(),
+                LLSDValue::String("secret".to_string()),
+            ),
+            ("c".to_string(), LLSDValue::Integer(3)),
+        ]
+        .iter()
+        .cloned()
+        .collect(),
+    );
+    map.retain(|_, value| value.as_integer().is_some());
+    let mut keys: Vec<&str> = map.keys().collect();
+    keys.sort_unstable();
+    assert_eq!(vec!["a", "c"], keys);
+    let mut array = LLSDValue::Array(vec![
+        LLSDValue::String("x".to_string()),
+        LLSDValue::String(String::new()),
+        LLSDValue::Integer(2),
+        LLSDValue::String("y".to_string()),
+    ]);
+    array.retain(|key, value| {
+        assert!(key.is_none());
+        matches!(value, LLSDValue::String(s) if !s.is_empty())
+    });
+    assert_eq!(
+        LLSDValue::Array(vec![
+            LLSDValue::String("x".to_string()),
+            LLSDValue::String("y".to_string())
+        ]),
+        array
+    );
+    let mut scalar = LLSDValue::Integer(1);
+    scalar.retain(|_, _| false);
+    assert_eq!(LLSDValue::Integer(1), scalar);
+}
+
+#[test]
+fn numericeqtest() {
+    let a = notation::parse(b"{'count':i5,'pos':[r1.0,i2,r3.5],'name':'x'}").unwrap();
+    let b = notation::parse(b"{'count':r5.0,'pos':[i1,r2.0,r3.5],'name':'x'}").unwrap();
+    assert!(a.numeric_eq(&b));
+    assert!(b.numeric_eq(&a));
+    assert_ne!(a, b); // == stays exact
+    assert!(!LLSDValue::Integer(5).numeric_eq(&LLSDValue::Real(5.5)));
+    assert!(!LLSDValue::Integer(5).numeric_eq(&LLSDValue::String("5".to_string())));
+    assert!(!LLSDValue::Integer(1).numeric_eq(&LLSDValue::Boolean(true)));
+    //  Structure must still match.
+    let c = notation::parse(b"{'count':r5.0,'pos':[i1,r2.0],'name':'x'}").unwrap();
+    assert!(!a.numeric_eq(&c));
+    let d = notation::parse(b"{'count':r5.0,'pos':[i1,r2.0,r3.5],'other':'x'}").unwrap();
+    assert!(!a.numeric_eq(&d));
+}
+
+#[test]
+fn eqignoringundefinedtest() {
+    let full = LLSDValue::Map(
+        [
+            ("a".to_string(), LLSDValue::Integer(1)),
+            ("optional".to_string(), LLSDValue::Undefined),
+            (
+                "inner".to_string(),
+                LLSDValue::Map(
+                    [("x".to_string(), LLSDValue::Undefined)]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                ),
+            ),
+        ]
+        .iter()
+        .cloned()
+        .collect(),
+    );
+    let sparse = LLSDValue::Map(
+        [
+            ("a".to_string(), LLSDValue::Integer(1)),
+            ("inner".to_string(), LLSDValue::Map(HashMap::new())),
+        ]
+        .iter()
+        .cloned()
+        .collect(),
+    );
+    assert!(full != sparse);
+    assert!(full.eq_ignoring_undefined(&sparse));
+    assert!(sparse.eq_ignoring_undefined(&full));
+    //  A real value is still a difference.
+    let mut other = sparse.clone();
+    other.set_path("/optional", LLSDValue::Integer(0)).unwrap();
+    assert!(!full.eq_ignoring_undefined(&other));
+    //  Arrays are not padded.
+    let a1 = LLSDValue::Array(vec![LLSDValue::Integer(1), LLSDValue::Undefined]);
+    let a2 = LLSDValue::Array(vec![LLSDValue::Integer(1)]);
+    assert!(!a1.eq_ignoring_undefined(&a2));
+}
+
+#[test]
+fn parsebooltexttest() {
+    for text in &["true", "TRUE", "True", "t", "T", "1", " 1.0", "true\n"] {
+        assert!(parse_bool_text(text).unwrap(), "{:?}", text);
+    }
+    for text in &["false", "FALSE", "False", "f", "F", "0", "0.0 ", "  false"] {
+        assert!(!parse_bool_text(text).unwrap(), "{:?}", text);
+    }
+    for text in &["", "yes", "2", "tru", "t r u e"] {
+        assert!(parse_bool_text(text).is_err(), "{:?}", text);
+    }
+}
+
+#[test]
+fn contenttypetest() {
+    for format in &[Format::Xml, Format::Binary, Format::Notation] {
+        assert_eq!(
+            Some(*format),
+            Format::from_content_type(format.content_type())
+        );
+    }
+    assert_eq!(
+        Some(Format::Xml),
+        Format::from_content_type("Application/LLSD+XML; charset=utf-8")
+    );
+    assert_eq!(
+        Some(Format::Binary),
+        Format::from_content_type("text/html, application/llsd+binary;q=0.9, application/llsd+xml")
+    );
+    assert_eq!(None, Format::from_content_type("application/json"));
+    assert_eq!(None, Format::from_content_type(""));
+}
+
+#[test]
+fn fromreadertest() {
+    let val = LLSDValue::Array(vec![
+        LLSDValue::Integer(42),
+        LLSDValue::String("Hello".to_string()),
+    ]);
+    let binary = binary::to_bytes(&val).unwrap();
+    assert_eq!(
+        val,
+        LLSDValue::from_reader(std::io::Cursor::new(binary)).unwrap()
+    );
+    let xml = xml::to_xml_string(&val, true).unwrap();
+    assert_eq!(
+        val,
+        LLSDValue::from_reader(std::io::Cursor::new(xml.as_bytes())).unwrap()
+    );
+    //  Shorter than a binary header.
+    assert_eq!(
+        LLSDValue::Integer(1),
+        LLSDValue::from_reader(std::io::Cursor::new(b"i1")).unwrap()
+    );
+    assert!(LLSDValue::from_reader(std::io::Cursor::new(b"")).is_err());
+}
+
+#[test]
+fn mutatortest() {
+    let mut array = LLSDValue::Array(Vec::new());
+    array.array_push(1).unwrap();
+    array.array_push("two").unwrap();
+    assert_eq!(
+        LLSDValue::Array(vec![
+            LLSDValue::Integer(1),
+            LLSDValue::String("two".to_string())
+        ]),
+        array
+    );
+    let mut map = LLSDValue::Map(HashMap::new());
+    assert_eq!(None, map.map_insert("a", 1.5).unwrap());
+    assert_eq!(
+        Some(LLSDValue::Real(1.5)),
+        map.map_insert("a".to_string(), true).unwrap()
+    );
+    assert_eq!(Some(&LLSDValue::Boolean(true)), map.pointer("/a"));
+    //  Wrong kind of value.
+    let mut scalar = LLSDValue::Integer(1);
+    assert!(scalar.array_push(2).is_err());
+    assert!(scalar.map_insert("a", 2).is_err());
+    assert!(array.map_insert("a", 2).is_err());
+    assert!(map.array_push(2).is_err());
+}
+
+#[test]
+fn entrytest() {
+    //  Insert if absent, starting from Undefined.
+    let mut stats = LLSDValue::Undefined;
+    *stats.entry("count") = 5.into();
+    *stats.entry("region").entry("name") = "Vallone".into();
+    assert_eq!(Some(&LLSDValue::Integer(5)), stats.pointer("/count"));
+    assert_eq!(
+        Some(&LLSDValue::String("Vallone".to_string())),
+        stats.pointer("/region/name")
+    );
+    //  Modify if present.
+    if let LLSDValue::Integer(n) = stats.entry("count") {
+        *n += 1;
+    }
+    assert_eq!(Some(&LLSDValue::Integer(6)), stats.pointer("/count"));
+    assert_eq!(2, stats.as_map().unwrap().len());
+    //  Reading an absent entry leaves an Undefined behind.
+    assert_eq!(&LLSDValue::Undefined, stats.entry("new"));
+    assert!(stats.contains_key("new"));
+}
+
+#[test]
+#[should_panic]
+fn entryscalartest() {
+    let mut scalar = LLSDValue::Integer(1);
+    let _ = scalar.entry("a");
+}
+
+#[test]
+fn boolcoercedtest() {
+    assert_eq!(Some(true), LLSDValue::Boolean(true).as_bool_coerced());
+    assert_eq!(Some(false), LLSDValue::Boolean(false).as_bool_coerced());
+    assert_eq!(Some(true), LLSDValue::Integer(1).as_bool_coerced());
+    assert_eq!(Some(true), LLSDValue::Integer(-5).as_bool_coerced());
+    assert_eq!(Some(false), LLSDValue::Integer(0).as_bool_coerced());
+    assert_eq!(Some(true), LLSDValue::Real(1.0).as_bool_coerced());
+    assert_eq!(Some(false), LLSDValue::Real(0.0).as_bool_coerced());
+    assert_eq!(None, LLSDValue::Real(f64::NAN).as_bool_coerced());
+    assert_eq!(
+        Some(true),
+        LLSDValue::String("true".to_string()).as_bool_coerced()
+    );
+    assert_eq!(
+        Some(false),
+        LLSDValue::String("F".to_string()).as_bool_coerced()
+    );
+    assert_eq!(
+        None,
+        LLSDValue::String("maybe".to_string()).as_bool_coerced()
+    );
+    assert_eq!(None, LLSDValue::Undefined.as_bool_coerced());
+    assert_eq!(None, LLSDValue::Array(Vec::new()).as_bool_coerced());
+    assert_eq!(None, LLSDValue::Map(HashMap::new()).as_bool_coerced());
+}
+
+#[test]
+fn emptybinarytest() {
+    //  Empty binary, alone and inside containers, through every format.
+    let empty = LLSDValue::Binary(Vec::new());
+    let test1 = LLSDValue::Array(vec![
+        empty.clone(),
+        LLSDValue::Map(
+            [("blob".to_string(), empty.clone())]
+                .iter()
+                .cloned()
+                .collect(),
+        ),
+        LLSDValue::Integer(1),
+    ]);
+    for val in &[empty.clone(), test1] {
+        let xml = xml::to_xml_string(val, false).unwrap();
+        let bin = binary::to_bytes(val).unwrap();
+        let notation_bytes = notation::to_bytes(val).unwrap();
+        let notation_string = notation::to_string(val).unwrap();
+        assert_eq!(*val, LLSDValue::parse(xml.as_bytes()).unwrap());
+        assert_eq!(*val, LLSDValue::parse(&bin).unwrap());
+        assert_eq!(*val, LLSDValue::parse(&notation_bytes).unwrap());
+        assert_eq!(*val, LLSDValue::parse(notation_string.as_bytes()).unwrap());
+    }
+    //  Empty binary is not Undefined.
+    let xml = xml::to_xml_string(&empty, false).unwrap();
+    assert_ne!(
+        LLSDValue::Undefined,
+        LLSDValue::parse(xml.as_bytes()).unwrap()
+    );
+    assert_eq!(
+        b"<? LLSD/Binary ?>\nb\x00\x00\x00\x00",
+        &binary::to_bytes(&empty).unwrap()[..]
+    );
+    assert_eq!(empty, notation::parse(b"b(0)\"\"").unwrap());
+    assert_eq!(empty, notation::parse(b"b16\"\"").unwrap());
+}
+
+#[test]
+fn diagnosticstest() {
+    //  The same tree in each format: {'a': 1, 'a': 2, 'list': [{'x/y': 3, 'x/y': 4}]}
+    let notation = b"{'a':i1,'a':i2,'list':[{'x/y':i3,'x/y':i4}]}".to_vec();
+    let xml = b"<?xml version=\"1.0\"?><llsd><map><key>a</key><integer>1</integer>\
+        <key>a</key><integer>2</integer><key>list</key><array><map>\
+        <key>x/y</key><integer>3</integer><key>x/y</key><integer>4</integer>\
+        </map></array></map></llsd>"
+        .to_vec();
+    let mut binary = binary::LLSDBINARYPREFIX.to_vec();
+    binary.extend_from_slice(b"{\x00\x00\x00\x03k\x00\x00\x00\x01ai\x00\x00\x00\x01");
+    binary.extend_from_slice(b"k\x00\x00\x00\x01ai\x00\x00\x00\x02k\x00\x00\x00\x04list");
+    binary.extend_from_slice(
+        b"[\x00\x00\x00\x01{\x00\x00\x00\x02k\x00\x00\x00\x03x/yi\x00\x00\x00\x03",
+    );
+    binary.extend_from_slice(b"k\x00\x00\x00\x03x/yi\x00\x00\x00\x04}]}");
+    for msg in &[notation, xml, binary] {
+        let (val, diags) = LLSDValue::parse_with_diagnostics(msg).unwrap();
+        //  Lenient as always: the last value wins.
+        assert_eq!(Some(&LLSDValue::Integer(2)), val.pointer("/a"));
+        assert_eq!(Some(&LLSDValue::Integer(4)), val.pointer("/list/0/x~1y"));
+        assert_eq!(
+            vec![
+                Diagnostic::DuplicateKey {
+                    key: "a".to_string(),
+                    path: "/a".to_string()
+                },
+                Diagnostic::DuplicateKey {
+                    key: "x/y".to_string(),
+                    path: "/list/0/x~1y".to_string()
+                },
+            ],
+            diags
+        );
+        //  The ordinary parse agrees.
+        assert_eq!(val, LLSDValue::parse(msg).unwrap());
+    }
+    //  Nothing to report.
+    let (_, diags) = LLSDValue::parse_with_diagnostics(b"{'a':i1,'b':[i2]}").unwrap();
+    assert!(diags.is_empty());
+}
+
+#[test]
+fn normalizeuristest() {
+    let mut val = notation::parse(
+        b"{'seed_capability':'http://example.com/cap','name':'http://not/a/uri/field',\
+        'regions':[{'seed_capability':'http://example.com/r1','id':i1}],\
+        'home':l'http://example.com/home'}",
+    )
+    .unwrap();
+    val.normalize_uris(&["seed_capability", "home", "id"]);
+    assert_eq!(
+        Some(&LLSDValue::URI("http://example.com/cap".to_string())),
+        val.pointer("/seed_capability")
+    );
+    assert_eq!(
+        Some(&LLSDValue::URI("http://example.com/r1".to_string())),
+        val.pointer("/regions/0/seed_capability")
+    );
+    //  Other keys, values already URIs, and non-strings are unchanged.
+    assert_eq!(
+        Some(&LLSDValue::String("http://not/a/uri/field".to_string())),
+        val.pointer("/name")
+    );
+    assert_eq!(
+        Some(&LLSDValue::URI("http://example.com/home".to_string())),
+        val.pointer("/home")
+    );
+    assert_eq!(Some(&LLSDValue::Integer(1)), val.pointer("/regions/0/id"));
+}
+
+#[test]
+fn binaryleadingwhitespacetest() {
+    let val = LLSDValue::Array(vec![
+        LLSDValue::Integer(1),
+        LLSDValue::String("a".to_string()),
+    ]);
+    let bytes = binary::to_bytes(&val).unwrap();
+    for prefix in &[&b"\n"[..], b"\r\n  \t", b"\xEF\xBB\xBF", b"\xEF\xBB\xBF\n"] {
+        let mut msg = prefix.to_vec();
+        msg.extend_from_slice(&bytes);
+        assert_eq!(
+            (val.clone(), Format::Binary),
+            LLSDValue::parse_with_format(&msg).unwrap()
+        );
+    }
+    //  Anything else before the header is not binary.
+    let mut msg = b"x".to_vec();
+    msg.extend_from_slice(&bytes);
+    assert!(LLSDValue::parse(&msg).is_err());
+}
+
+#[test]
+fn checkserializabletest() {
+    let val =
+        notation::parse(b"{'name':'Vallone','list':[i1,r1.5,'ok'],'blob':b16\"00ff\"}").unwrap();
+    for format in &[Format::Binary, Format::Xml, Format::Notation] {
+        assert!(val.check_serializable(*format).is_ok());
+    }
+    //  Control character, only a problem for XML.
+    let mut bad = val.clone();
+    bad.set_path("/list/2", LLSDValue::String("bell\u{7}".to_string()))
+        .unwrap();
+    let err = bad.check_serializable(Format::Xml).unwrap_err();
+    println!("XML: {}", err);
+    assert!(err.to_string().contains("\"/list/2\""));
+    assert!(bad.check_serializable(Format::Binary).is_ok());
+    //  The XML writer agrees.
+    assert!(xml::to_xml_string(&bad, false).is_err());
+    //  Oversized for binary. Real limit is 4GB, so test with a small one.
+    let err = val
+        .check_serializable_at(Format::Binary, "", 3)
+        .unwrap_err();
+    println!("Binary: {}", err);
+    assert!(err.to_string().contains("length"));
+    assert!(val.check_serializable_at(Format::Notation, "", 3).is_ok());
+    //  Non-finite reals are fine in all LLSD formats.
+    let nan = LLSDValue::Array(vec![
+        LLSDValue::Real(f64::NAN),
+        LLSDValue::Real(f64::INFINITY),
+    ]);
+    for format in &[Format::Binary, Format::Xml, Format::Notation] {
+        assert!(nan.check_serializable(*format).is_ok());
+    }
+    //  Dates beyond what can be written as text.
+    let date = LLSDValue::Date(1.0e20);
+    assert!(date.check_serializable(Format::Xml).is_err());
+    assert!(date.check_serializable(Format::Notation).is_err());
+    assert!(date.check_serializable(Format::Binary).is_ok());
+    //  Deep nesting is checked without recursing.
+    let mut deep = LLSDValue::Array(vec![LLSDValue::Date(1.0e20)]);
+    for _ in 0..20_000 {
+        deep = LLSDValue::Array(vec![deep]);
+    }
+    let err = deep.check_serializable(Format::Xml).unwrap_err();
+    assert!(err.to_string().contains(&"/0".repeat(20_001)));
+    assert!(deep.check_serializable(Format::Binary).is_ok());
+    //  Take it apart a level at a time. Dropping it whole would recurse.
+    while let LLSDValue::Array(mut items) = deep {
+        deep = items.pop().unwrap_or(LLSDValue::Undefined);
+    }
+}
+
+#[test]
+fn hasheqtest() {
+    use std::collections::HashSet;
+    //  -0.0 and 0.0 are equal, and hash alike.
+    let mut set = HashSet::new();
+    assert!(set.insert(LLSDValue::Real(0.0)));
+    assert!(!set.insert(LLSDValue::Real(-0.0)));
+    assert_eq!(1, set.len());
+    //  Still equal after a binary round trip, which keeps the sign bit.
+    let bytes = binary::to_bytes(&LLSDValue::Real(-0.0)).unwrap();
+    let negzero = LLSDValue::parse(&bytes).unwrap();
+    assert!(negzero.as_real().unwrap().is_sign_negative());
+    assert!(set.contains(&negzero));
+    //  NaN equals NaN, so == is an equivalence.
+    assert_eq!(LLSDValue::Real(f64::NAN), LLSDValue::Real(-f64::NAN));
+    assert!(set.insert(LLSDValue::Real(f64::NAN)));
+    assert!(!set.insert(LLSDValue::Real(f64::from_bits(f64::NAN.to_bits() | 1))));
+    assert!(set.insert(LLSDValue::Date(0.0))); // not the same as a Real
+    assert_ne!(LLSDValue::Real(1.0), LLSDValue::Integer(1));
+    //  Maps hash the same whatever the insertion order.
+    let a: LLSDValue = (0..20)
+        .map(|n| (n.to_string(), LLSDValue::Integer(n)))
+        .collect();
+    let b: LLSDValue = (0..20)
+        .rev()
+        .map(|n| (n.to_string(), LLSDValue::Integer(n)))
+        .collect();
+    assert_eq!(a, b);
+    assert!(set.insert(a));
+    assert!(!set.insert(b));
+    assert_eq!(4, set.len());
+}
+
+#[test]
+fn containerviewtest() {
+    let array = notation::parse(b"[i1,'two',r3.0]").unwrap();
+    assert_eq!(3, array.array_elements().len());
+    assert_eq!(LLSDValue::Integer(1), array.array_elements()[0]);
+    assert_eq!(0, array.map_entries().count());
+    let map = notation::parse(b"{'a':i1,'b':[i2]}").unwrap();
+    let mut entries: Vec<(&str, &LLSDValue)> = map.map_entries().collect();
+    entries.sort_by_key(|(k, _)| *k);
+    assert_eq!(
+        vec![
+            ("a", &LLSDValue::Integer(1)),
+            ("b", &LLSDValue::Array(vec![LLSDValue::Integer(2)]))
+        ],
+        entries
+    );
+    assert!(map.array_elements().is_empty());
+    //  Scalars look like empty containers.
+    let scalar = LLSDValue::Integer(5);
+    assert!(scalar.array_elements().is_empty());
+    assert_eq!(0, scalar.map_entries().count());
+}
+
+#[test]
+fn strictlenienttest() {
+    //  A hex integer in XML is a relaxation.
+    let hexint = "<?xml version=\"1.0\" ?><llsd><integer>0x10</integer></llsd>";
+    assert!(xml::parse_with_options(hexint, &ParseOptions::strict()).is_err());
+    assert_eq!(
+        LLSDValue::Integer(16),
+        xml::parse_with_options(hexint, &ParseOptions::lenient()).unwrap()
+    );
+    //  So is a map key without the 'k' prefix in binary.
+    let options = binary::BinaryOptions {
+        emit_key_prefix: false,
+    };
+    let val = notation::parse(b"{'a':i1}").unwrap();
+    let nokey = binary::to_bytes_with_options(&val, &options).unwrap();
+    let body = &nokey[binary::LLSDBINARYPREFIX.len()..];
+    assert!(binary::parse_array_with_options(body, &ParseOptions::strict()).is_err());
+    assert_eq!(
+        val,
+        binary::parse_array_with_options(body, &ParseOptions::lenient()).unwrap()
+    );
+    //  NUL in a string is valid, but refused by strict.
+    let nul = binary::to_bytes(&LLSDValue::String("a\0b".to_string())).unwrap();
+    let body = &nul[binary::LLSDBINARYPREFIX.len()..];
+    assert!(binary::parse_array_with_options(body, &ParseOptions::strict()).is_err());
+    assert!(binary::parse_array_with_options(body, &ParseOptions::lenient()).is_ok());
+}
+
+#[test]
+fn parselimitstest() {
+    let strict = ParseOptions::strict();
+    let depth = |s: &str| format!("{}{}", "[".repeat(s.len()), "]".repeat(s.len()));
+    //  Nesting, in every format. 64 levels is fine for strict, 65 is not.
+    let ok = notation::parse(depth(&"x".repeat(64)).as_bytes()).unwrap();
+    let over = notation::parse(depth(&"x".repeat(65)).as_bytes()).unwrap();
+    let formats: [fn(&LLSDValue) -> Vec<u8>; 3] = [
+        |v| binary::to_bytes(v).unwrap(),
+        |v| xml::to_xml_string(v, false).unwrap().into_bytes(),
+        |v| notation::to_bytes(v).unwrap(),
+    ];
+    for generate in formats.iter() {
+        assert!(LLSDValue::parse_with_options(&generate(&ok), &strict).is_ok());
+        let err = LLSDValue::parse_with_options(&generate(&over), &strict).unwrap_err();
+        println!("Error as expected: {}", err);
+        assert_eq!(64, err.downcast_ref::<NestingTooDeep>().unwrap().limit);
+        //  The default limit is higher.
+        assert!(LLSDValue::parse(&generate(&over)).is_ok());
+    }
+    //  Size, in every format.
+    let small = ParseOptions {
+        max_size: 10,
+        ..Default::default()
+    };
+    let val = notation::parse(b"['abcdefghijklmnop']").unwrap();
+    for generate in formats.iter() {
+        let err = LLSDValue::parse_with_options(&generate(&val), &small).unwrap_err();
+        assert_eq!(10, err.downcast_ref::<InputTooLarge>().unwrap().limit);
+    }
+    assert!(LLSDValue::parse_with_options(b"i1", &small).is_ok());
+    //  A huge declared length in a tiny input fails without allocating it.
+    for bad in [&b"b\xff\xff\xff\xff"[..], &b"s\xff\xff\xff\xfeabc"[..]].iter() {
+        let err = binary::parse_array(bad).unwrap_err();
+        assert!(err.to_string().contains("unexpected end of data"));
+        assert!(binary::parse_read(&mut std::io::Cursor::new(bad)).is_err());
+    }
+}
+
+#[test]
+fn classifyerrortest() {
+    let xmldoc = |body: &str| format!("<?xml version=\"1.0\" ?><llsd>{}</llsd>", body);
+    let kind = |result: Result<LLSDValue, Error>| classify_error(&result.unwrap_err());
+    //  Truncated, in each format.
+    assert_eq!(
+        LLSDErrorKind::Truncated,
+        kind(binary::parse_array(b"[\x00\x00\x00\x02i\x00"))
+    );
+    assert_eq!(
+        LLSDErrorKind::Truncated,
+        kind(notation::parse(b"{'a':[i1,"))
+    );
+    assert_eq!(
+        LLSDErrorKind::Truncated,
+        kind(xml::parse(
+            "<?xml version=\"1.0\" ?><llsd><array><integer>1</integer>"
+        ))
+    );
+    //  Encoding
+    assert_eq!(
+        LLSDErrorKind::Encoding,
+        kind(binary::parse_array(b"s\x00\x00\x00\x01\xff"))
+    );
+    assert_eq!(
+        LLSDErrorKind::Encoding,
+        kind(xml::parse(&xmldoc("<binary>@@@</binary>")))
+    );
+    assert_eq!(LLSDErrorKind::Encoding, kind(notation::parse(b"b16'xyz'")));
+    //  Value
+    assert_eq!(
+        LLSDErrorKind::Value,
+        kind(xml::parse(&xmldoc("<integer>99999999999</integer>")))
+    );
+    assert_eq!(
+        LLSDErrorKind::Value,
+        kind(xml::parse(&xmldoc("<uuid>not a uuid</uuid>")))
+    );
+    assert_eq!(LLSDErrorKind::Value, kind(notation::parse(b"d'yesterday'")));
+    let err = xml::to_xml_string(&LLSDValue::Date(f64::NAN), false).unwrap_err();
+    assert_eq!(LLSDErrorKind::Value, classify_error(&err));
+    //  Syntax
+    assert_eq!(LLSDErrorKind::Syntax, kind(binary::parse_array(b"?")));
+    assert_eq!(LLSDErrorKind::Syntax, kind(notation::parse(b"[i1 i2]")));
+    assert_eq!(
+        LLSDErrorKind::Syntax,
+        kind(xml::parse(&xmldoc(
+            "<integer>1</integer><integer>2</integer>"
+        )))
+    );
+    //  I/O
+    let err: Error = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "no").into();
+    assert_eq!(LLSDErrorKind::Io, classify_error(&err));
+    //  Limits
+    assert_eq!(
+        LLSDErrorKind::Limit,
+        kind(notation::parse("[".repeat(1000).as_bytes()))
+    );
+    let small = ParseOptions {
+        max_size: 1,
+        ..Default::default()
+    };
+    assert_eq!(
+        LLSDErrorKind::Limit,
+        kind(LLSDValue::parse_with_options(b"i12", &small))
+    );
+    //  The kind comes from the error's type, not its wording.
+    let err = binary::parse_array(b"i\x00").unwrap_err();
+    assert_eq!(
+        LLSDErrorKind::Truncated,
+        err.downcast_ref::<LLSDError>().unwrap().kind
+    );
+    let reworded: Error = LLSDError {
+        kind: LLSDErrorKind::Value,
+        message: "no keywords here".to_string(),
+    }
+    .into();
+    assert_eq!(LLSDErrorKind::Value, classify_error(&reworded));
+    assert_eq!(
+        LLSDErrorKind::Syntax,
+        classify_error(&anyhow!("unexpected end of data"))
+    );
+}
+
+#[test]
+fn parsewithcontenttypetest() {
+    let val = notation::parse(b"{'a':[i1,r2.5,'three']}").unwrap();
+    let xmlbody = xml::to_xml_string(&val, true).unwrap();
+    let binbody = binary::to_bytes(&val).unwrap();
+    let notationbody = notation::to_string(&val).unwrap();
+    let parse = LLSDValue::parse_with_content_type;
+    assert_eq!(
+        val,
+        parse(xmlbody.as_bytes(), "application/llsd+xml").unwrap()
+    );
+    assert_eq!(val, parse(&binbody, "application/llsd+binary").unwrap());
+    assert_eq!(
+        val,
+        parse(
+            &binbody[binary::LLSDBINARYPREFIX.len()..],
+            "Application/LLSD+Binary"
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        val,
+        parse(notationbody.as_bytes(), "application/llsd+notation").unwrap()
+    );
+    //  Headerless notation with parameters on the type.
+    assert_eq!(
+        LLSDValue::Integer(5),
+        parse(b"i5", "application/llsd+notation; charset=utf-8").unwrap()
+    );
+    //  The header wins over sniffing. This binary body is not XML.
+    assert!(parse(&binbody, "application/llsd+xml").is_err());
+    //  Unknown types are sniffed.
+    assert_eq!(val, parse(xmlbody.as_bytes(), "text/plain").unwrap());
+    assert_eq!(val, parse(&binbody, "application/octet-stream").unwrap());
+    assert_eq!(val, parse(notationbody.as_bytes(), "").unwrap());
+}
+
+#[test]
+fn truncatestringstest() {
+    let mut val = notation::parse(
+        "{'name':'Vallone Estates','emoji':'ab😀😀😀😀','blob':b64'AAECAwQFBgc=','n':i123456789,\
+        'uri':l'http://example.com','longkey_one':i1,'longkey_two':i2,'list':['abcdefgh','é€😀xyz']}"
+            .as_bytes(),
+    )
+    .unwrap();
+    val.truncate_strings(5);
+    println!("Truncated: {:?}", val);
+    let expected = notation::parse(
+        "{'name':'Vallo','emoji':'ab😀😀😀','blob':b64'AAECAwQFBgc=','n':i123456789,\
+        'uri':l'http:','longk':i1,'list':['abcde','é€😀xy']}"
+            .as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(expected, val);
+    //  Shorter strings are unchanged.
+    let mut short = LLSDValue::String("abc".to_string());
+    short.truncate_strings(5);
+    assert_eq!(LLSDValue::String("abc".to_string()), short);
+    let mut none = LLSDValue::String("abc".to_string());
+    none.truncate_strings(0);
+    assert_eq!(LLSDValue::String(String::new()), none);
+}
+
+#[test]
+fn sortedkeystest() {
+    let keys = ["delta", "Alpha", "charlie", "bravo", "alpha"];
+    let mut forward = HashMap::new();
+    for (n, key) in keys.iter().enumerate() {
+        forward.insert(key.to_string(), LLSDValue::Integer(n as i32));
+    }
+    let mut backward = HashMap::with_capacity(100);
+    for (n, key) in keys.iter().enumerate().rev() {
+        backward.insert(key.to_string(), LLSDValue::Integer(n as i32));
+    }
+    let forward = LLSDValue::Map(forward);
+    let backward = LLSDValue::Map(backward);
+    //  Lexical, by bytes, so upper case first.
+    let expected = vec!["Alpha", "alpha", "bravo", "charlie", "delta"];
+    assert_eq!(expected, forward.sorted_keys());
+    assert_eq!(expected, backward.sorted_keys());
+    assert_eq!(forward.sorted_entries(), backward.sorted_entries());
+    assert_eq!(
+        ("delta", &LLSDValue::Integer(0)),
+        forward.sorted_entries()[4]
+    );
+    //  Non-maps have none.
+    assert!(LLSDValue::Array(vec![LLSDValue::Integer(1)])
+        .sorted_keys()
+        .is_empty());
+    assert!(LLSDValue::Undefined.sorted_entries().is_empty());
+}
+
+#[test]
+fn parselatin1xmltest() {
+    //  "Café" with a Latin-1 é, which is not valid UTF-8.
+    let msg =
+        b"<?xml version=\"1.0\" ?><llsd><map><key>name</key><string>Caf\xe9</string></map></llsd>";
+    let err = LLSDValue::parse(msg).unwrap_err();
+    println!("Error as expected: {}", err);
+    assert!(err.to_string().starts_with("XML LLSD, invalid UTF-8"));
+    assert!(LLSDValue::parse_with_options(msg, &ParseOptions::strict()).is_err());
+    let val = LLSDValue::parse_with_options(msg, &ParseOptions::lenient()).unwrap();
+    assert_eq!(
+        Some(&LLSDValue::String("Caf\u{FFFD}".to_string())),
+        val.pointer("/name")
+    );
+    //  Valid input is the same either way.
+    let good = "<?xml version=\"1.0\" ?><llsd><string>Café</string></llsd>".as_bytes();
+    assert_eq!(
+        LLSDValue::parse(good).unwrap(),
+        LLSDValue::parse_with_options(good, &ParseOptions::lenient()).unwrap()
+    );
+}
+
+#[test]
+fn scalarcontainertest() {
+    let scalars = [
+        LLSDValue::Undefined,
+        LLSDValue::Boolean(true),
+        LLSDValue::Integer(1),
+        LLSDValue::Real(1.5),
+        LLSDValue::UUID(uuid::Uuid::nil()),
+        LLSDValue::String("s".to_string()),
+        LLSDValue::URI("http://example.com".to_string()),
+        LLSDValue::Date(0.0),
+        LLSDValue::Binary(vec![1, 2]),
+    ];
+    for val in scalars.iter() {
+        assert!(val.is_scalar(), "{:?}", val);
+        assert!(!val.is_container(), "{:?}", val);
+    }
+    //  Empty containers are still containers.
+    let containers = [LLSDValue::Map(HashMap::new()), LLSDValue::Array(Vec::new())];
+    for val in containers.iter() {
+        assert!(val.is_container(), "{:?}", val);
+        assert!(!val.is_scalar(), "{:?}", val);
+    }
+}
+
+#[test]
+fn countnodestest() {
+    let val = notation::parse(b"{'a':[i1,i2,{'b':[]}],'c':'x'}").unwrap();
+    //  Outer map, array, 1, 2, inner map, empty array, 'x'
+    assert_eq!(7, val.count_nodes());
+    //  Outer map, array, inner map, empty array
+    assert_eq!(4, val.max_depth());
+    assert_eq!(1, LLSDValue::Undefined.count_nodes());
+    assert_eq!(1, LLSDValue::Undefined.max_depth());
+    assert_eq!(1, LLSDValue::Array(vec![]).max_depth());
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracingtest() {
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span;
+    //  Minimal subscriber which keeps the text of each event.
+    struct Collector(Arc<Mutex<Vec<String>>>);
+    struct Fields(String);
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0 += &format!("{}={:?} ", field.name(), value);
+        }
+    }
+    impl tracing::Subscriber for Collector {
+        fn enabled(&self, _: &tracing::Metadata) -> bool {
+            true
+        }
+        fn new_span(&self, _: &span::Attributes) -> span::Id {
+            span::Id::from_u64(1)
+        }
+        fn record(&self, _: &span::Id, _: &span::Record) {}
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn event(&self, event: &tracing::Event) {
+            let mut fields = Fields(String::new());
+            event.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0);
+        }
+        fn enter(&self, _: &span::Id) {}
+        fn exit(&self, _: &span::Id) {}
+    }
+    let events = Arc::new(Mutex::new(Vec::new()));
+    tracing::subscriber::with_default(Collector(events.clone()), || {
+        assert!(binary::parse_array(b"[\x00\x00\x00\x01?]").is_err());
+        assert!(xml::parse("<?xml version=\"1.0\"?><llsd><map><key>a</key></map></llsd>").is_err());
+    });
+    let events = events.lock().unwrap();
+    for event in events.iter() {
+        println!("Event: {}", event);
+    }
+    assert!(events
+        .iter()
+        .any(|e| e.contains("format=Binary") && e.contains("unexpected type code")));
+    assert!(events
+        .iter()
+        .any(|e| e.contains("format=Xml") && e.contains("has no value")));
+    assert!(events.iter().any(|e| e.contains("binary LLSD value")));
+}
+
+#[test]
+fn unixmillistest() {
+    let ms: i64 = 1_760_000_000_123; // October 2025, with a fractional second
+    let date = LLSDValue::from_unix_millis(ms);
+    assert_eq!(Some(ms), date.as_unix_millis());
+    //  Before 1970.
+    assert_eq!(
+        Some(-1_500),
+        LLSDValue::from_unix_millis(-1_500).as_unix_millis()
+    );
+    //  1233.999.. rounds.
+    assert_eq!(Some(1_234), LLSDValue::Date(1.234).as_unix_millis());
+    //  Through each format. XML and notation keep milliseconds.
+    for generated in [
+        binary::to_bytes(&date).unwrap(),
+        xml::to_xml_string(&date, false).unwrap().into_bytes(),
+        notation::to_string(&date).unwrap().into_bytes(),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            Some(ms),
+            LLSDValue::parse(generated).unwrap().as_unix_millis()
+        );
+    }
+    //  Not a date, or not representable.
+    assert_eq!(None, LLSDValue::Real(1.0).as_unix_millis());
+    assert_eq!(None, LLSDValue::Date(f64::NAN).as_unix_millis());
+    assert_eq!(None, LLSDValue::Date(1.0e20).as_unix_millis());
+}
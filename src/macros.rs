@@ -0,0 +1,116 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Macros.
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+
+/// Extract several typed fields from an LLSD map at once.
+///
+/// ```
+/// # use llsd::{llsd_get, LLSDValue};
+/// # let mut m = LLSDValue::Map(Default::default());
+/// # m.map_insert("region_id", llsd::uuid::Uuid::nil()).unwrap();
+/// # m.map_insert("sim fps", 44.5).unwrap();
+/// let (id, fps) = llsd_get!(m, "region_id": uuid, "sim fps": real).unwrap();
+/// # assert_eq!(44.5, fps);
+/// ```
+///
+/// Types are the LLSD type names boolean, integer, real, string, and uuid,
+/// giving bool, i32, f64, String, and uuid::Uuid. Reals accept integers.
+/// The result is a tuple of the values, or one error listing every field
+/// which was missing or of the wrong type.
+#[macro_export]
+macro_rules! llsd_get {
+    ($val:expr, $($key:literal : $ty:ident),+ $(,)?) => {{
+        let val: &$crate::LLSDValue = &$val;
+        //  Check every field first, so that all problems are reported.
+        let errors: Vec<String> = vec![
+            $( $crate::llsd_get!(@field val, $key, $ty).err().map(|e| e.to_string()) ),+
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if errors.is_empty() {
+            (|| -> Result<_, $crate::macros::__private::Error> {
+                Ok(( $( $crate::llsd_get!(@field val, $key, $ty)?, )+ ))
+            })()
+        } else {
+            Err($crate::macros::__private::anyhow!("{}", errors.join("; ")))
+        }
+    }};
+    (@field $val:ident, $key:literal, boolean) => { $val.get_as::<bool>($key) };
+    (@field $val:ident, $key:literal, integer) => { $val.get_as::<i32>($key) };
+    (@field $val:ident, $key:literal, real) => { $val.get_as::<f64>($key) };
+    (@field $val:ident, $key:literal, string) => { $val.get_as::<String>($key) };
+    (@field $val:ident, $key:literal, uuid) => { $val.get_as::<$crate::uuid::Uuid>($key) };
+}
+
+/// Trace event from the parsers, with feature "tracing". Otherwise nothing,
+/// and the arguments are not evaluated.
+#[cfg(feature = "tracing")]
+macro_rules! llsd_trace {
+    ($($arg:tt)*) => { tracing::trace!($($arg)*) };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! llsd_trace {
+    ($($arg:tt)*) => {};
+}
+
+/// Error of the given `LLSDErrorKind`, with a message formatted as by anyhow!,
+/// so that `classify_error` need not look at the text.
+macro_rules! llsd_error {
+    ($kind:ident, $($arg:tt)*) => {
+        anyhow::Error::from($crate::LLSDError {
+            kind: $crate::LLSDErrorKind::$kind,
+            message: format!($($arg)*),
+        })
+    };
+}
+
+/// Names used by macro expansions, so that users need not depend on anyhow.
+#[doc(hidden)]
+pub mod __private {
+    pub use anyhow::{anyhow, Error};
+}
+
+// Unit tests
+
+#[test]
+fn llsdgettest() {
+    const TESTXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+  <key>region_id</key><uuid>67153d5b-3659-afb4-8510-adda2c034649</uuid>
+  <key>sim fps</key><real>44.5</real>
+  <key>agents</key><integer>3</integer>
+  <key>name</key><string>Region</string>
+</map>
+</llsd>
+"#;
+    let m = crate::LLSDValue::parse(TESTXML.as_bytes()).unwrap();
+    let (id, fps, agents) =
+        llsd_get!(m, "region_id": uuid, "sim fps": real, "agents": integer).unwrap();
+    assert_eq!("67153d5b-3659-afb4-8510-adda2c034649", id.to_string());
+    assert_eq!(44.5, fps);
+    assert_eq!(3, agents);
+    let (name,) = llsd_get!(m, "name": string).unwrap();
+    assert_eq!("Region", name);
+    //  All the problems are reported together.
+    let err = llsd_get!(m, "region_id": uuid, "missing": real, "name": integer, "agents": boolean)
+        .unwrap_err()
+        .to_string();
+    println!("Error as expected: {}", err);
+    assert!(err.contains("\"missing\" is missing"));
+    assert!(err.contains("\"name\""));
+    assert!(err.contains("\"agents\""));
+    assert!(!err.contains("region_id"));
+    assert!(llsd_get!(crate::LLSDValue::Integer(1), "a": integer).is_err());
+}
@@ -0,0 +1,148 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Migration of LLSD trees between versions of a schema.
+//
+//  A migration is a list of rules, (from_path, to_path), each of which
+//  moves the value at one path to another. Paths are in the syntax of
+//  LLSDValue::pointer. Rules are applied in order, so later rules see
+//  the results of earlier ones.
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use super::{split_path, LLSDValue};
+use anyhow::{anyhow, Error};
+
+/// Apply move rules in order. Moving a path which is not present does nothing.
+/// Fails if a value cannot be stored at its new path, in which case it
+/// is put back where it was, at the same array index if it came from an
+/// array, and the rules already applied remain applied.
+pub fn migrate(val: &mut LLSDValue, rules: &[(&str, &str)]) -> Result<(), Error> {
+    migrate_rules(val, rules, false)
+}
+
+/// As `migrate`, but moving a path which is not present is an error.
+pub fn migrate_strict(val: &mut LLSDValue, rules: &[(&str, &str)]) -> Result<(), Error> {
+    migrate_rules(val, rules, true)
+}
+
+/// Apply the rules.
+fn migrate_rules(
+    val: &mut LLSDValue,
+    rules: &[(&str, &str)],
+    require_present: bool,
+) -> Result<(), Error> {
+    for (from, to) in rules {
+        let moved = match val.take_path(from) {
+            Some(moved) => moved,
+            None if require_present => {
+                return Err(anyhow!("LLSD migration: nothing at {:?} to move", from))
+            }
+            None => continue,
+        };
+        if let Err((e, moved)) = val.set_path_or_return(to, moved) {
+            put_back(val, from, moved);
+            return Err(anyhow!("LLSD migration from {:?} to {:?}: {}", from, to, e));
+        }
+    }
+    Ok(())
+}
+
+/// Undo a `take_path`. An array element goes back at its old index,
+/// moving the later ones up again, not over its neighbour.
+fn put_back(val: &mut LLSDValue, path: &str, moved: LLSDValue) {
+    //  The path was valid for take_path, and a failed set_path changes nothing,
+    //  so every step is still there.
+    let mut keys = split_path(path).unwrap_or_default();
+    let last = match keys.pop() {
+        Some(last) => last,
+        None => {
+            *val = moved; // the whole value was taken
+            return;
+        }
+    };
+    let mut target = val;
+    for key in keys {
+        target = match target {
+            LLSDValue::Map(m) => m.get_mut(&key),
+            LLSDValue::Array(a) => key.parse::<usize>().ok().and_then(move |n| a.get_mut(n)),
+            _ => None,
+        }
+        .expect("LLSD migration: path taken from has gone");
+    }
+    match target {
+        LLSDValue::Map(m) => {
+            m.insert(last, moved);
+        }
+        LLSDValue::Array(a) => {
+            let n = last.parse::<usize>().unwrap_or(a.len()).min(a.len());
+            a.insert(n, moved);
+        }
+        _ => unreachable!("LLSD migration: taken from a scalar"),
+    }
+}
+
+// Unit tests
+
+#[test]
+fn migratetest() {
+    const OLDXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+  <key>stats</key>
+  <map>
+    <key>sim fps</key><real>44.5</real>
+    <key>agents</key><integer>3</integer>
+  </map>
+  <key>name</key><string>Region</string>
+</map>
+</llsd>
+"#;
+    let mut val = crate::xml::parse(OLDXML).unwrap();
+    migrate(
+        &mut val,
+        &[
+            ("/stats/sim fps", "/stats/fps"), // rename nested key
+            ("/name", "/region/name"),        // move into a new map
+            ("/missing", "/somewhere"),       // not present, ignored
+        ],
+    )
+    .unwrap();
+    assert_eq!(Some(&LLSDValue::Real(44.5)), val.pointer("/stats/fps"));
+    assert!(val.pointer("/stats/sim fps").is_none());
+    assert_eq!(
+        Some(&LLSDValue::String("Region".to_string())),
+        val.pointer("/region/name")
+    );
+    assert!(val.pointer("/somewhere").is_none());
+    //  Strict mode wants everything present.
+    assert!(migrate_strict(&mut val, &[("/missing", "/somewhere")]).is_err());
+    //  Destination blocked by a scalar. Value stays where it was.
+    assert!(migrate(&mut val, &[("/stats/agents", "/region/name/agents")]).is_err());
+    assert_eq!(Some(&LLSDValue::Integer(3)), val.pointer("/stats/agents"));
+}
+
+#[test]
+fn migratearraytest() {
+    //  A failed move out of an array puts the element back at its index.
+    let mut val = crate::notation::parse(b"{'a':[i1,i2,i3],'s':'str'}").unwrap();
+    let before = val.clone();
+    assert!(migrate(&mut val, &[("/a/0", "/s/x")]).is_err());
+    assert_eq!(before, val);
+    assert!(migrate(&mut val, &[("/a/2", "/a/5")]).is_err());
+    assert_eq!(before, val);
+    //  Only element.
+    let mut val = crate::notation::parse(b"{'a':[i1],'s':'str'}").unwrap();
+    let before = val.clone();
+    assert!(migrate(&mut val, &[("/a/0", "/s/x")]).is_err());
+    assert_eq!(before, val);
+    //  Moves within an array see the shifted indices.
+    let mut val = crate::notation::parse(b"{'a':[i1,i2,i3]}").unwrap();
+    migrate(&mut val, &[("/a/0", "/a/1")]).unwrap();
+    assert_eq!(crate::notation::parse(b"{'a':[i2,i1]}").unwrap(), val);
+}
@@ -0,0 +1,1288 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Notation format.
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use super::xml::{format_date, parse_date};
+use super::{
+    parse_bool_text, sorted_map_entries, trace_error, Diagnostics, Format, LLSDError,
+    LLSDErrorKind, LLSDValue, ParseOptions,
+};
+use anyhow::{anyhow, Error};
+use std::collections::HashMap;
+use std::io::Write;
+//
+//  Constants
+//
+pub const LLSDNOTATIONPREFIX: &[u8] = b"<? llsd/notation ?>\n"; // notation LLSD prefix
+pub const LLSDNOTATIONSENTINEL: &[u8] = b"<? llsd/notation ?>"; // newline after it is optional
+const LOGSTRINGLIMIT: usize = 64; // log strings longer than this are summarized
+const LOGBINARYLIMIT: usize = 16; // log blobs longer than this are summarized
+
+/// Raw binary, b(N)"...", where N does not match the bytes inside the quotes.
+/// Usually a truncated or hand-edited file. Returned inside an anyhow::Error;
+/// use downcast_ref to get at the lengths.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryLengthMismatch {
+    pub declared: usize, // N
+    pub actual: usize,   // bytes up to the first closing quote
+    pub position: usize, // of the opening quote
+}
+
+impl std::fmt::Display for BinaryLengthMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Raw binary length mismatch at position {}: declared {} bytes, found {}",
+            self.position, self.declared, self.actual
+        )
+    }
+}
+
+impl std::error::Error for BinaryLengthMismatch {}
+
+///    Parse LLSD expressed in notation into an LLSD tree.
+///    The "<? llsd/notation ?>" header is optional.
+///    Input is bytes, not str, because raw binary and raw strings may hold anything.
+pub fn parse(b: &[u8]) -> Result<LLSDValue, Error> {
+    parse_with_options(b, &ParseOptions::default())
+}
+
+///    Parse LLSD expressed in notation, with options. Only the limits apply.
+pub fn parse_with_options(b: &[u8], options: &ParseOptions) -> Result<LLSDValue, Error> {
+    parse_diagnosed(b, options, &mut Diagnostics::new(false))
+}
+
+///    Parse notation, collecting diagnostics.
+pub(crate) fn parse_diagnosed(
+    b: &[u8],
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+) -> Result<LLSDValue, Error> {
+    trace_error(Format::Notation, parse_document(b, options, diags))
+}
+
+///    Parse the whole input as one value.
+fn parse_document(
+    b: &[u8],
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+) -> Result<LLSDValue, Error> {
+    options.check_size(b.len())?;
+    let mut lexer = Lexer::new(b);
+    //  Empty input, or just a header, is not a value. Undefined is spelled "!".
+    let val = match lexer.next_token()? {
+        None => {
+            return Err(llsd_error!(
+                Truncated,
+                "Notation LLSD, unexpected end of data, no value at position {}",
+                lexer.offset()
+            ))
+        }
+        token => parse_value_from(&mut lexer, token, options, diags, 0)?,
+    };
+    if lexer.next_token()?.is_some() {
+        return Err(anyhow!(
+            "Unexpected data after LLSD value at position {}",
+            lexer.offset()
+        ));
+    }
+    Ok(val)
+}
+
+/// One token of notation LLSD. Scalar values are complete tokens, already decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Undefined,
+    Boolean(bool),
+    Integer(i32),
+    Real(f64),
+    UUID(uuid::Uuid),
+    /// Quoted or raw s(N)"..." string. Also used for map keys.
+    String(String),
+    URI(String),
+    Date(f64),
+    Binary(Vec<u8>),
+    MapStart,
+    MapEnd,
+    ArrayStart,
+    ArrayEnd,
+    Colon,
+    Comma,
+}
+
+/// Tokenizer for notation LLSD, for use by parsers, syntax highlighters, and such.
+/// Handles the optional header, whitespace, quoting and escapes, and numeric literals.
+/// Yields tokens until the end of input or the first error.
+pub struct Lexer<'a> {
+    cursor: NotationCursor<'a>,
+    start: usize, // position of the last token returned
+    failed: bool, // stop after an error
+}
+
+impl<'a> Lexer<'a> {
+    /// Start of input. Skips the "<? llsd/notation ?>" header, if present.
+    pub fn new(b: &'a [u8]) -> Lexer<'a> {
+        let mut cursor = NotationCursor { b, pos: 0 };
+        cursor.skip_whitespace();
+        if cursor.rest().starts_with(LLSDNOTATIONSENTINEL) {
+            cursor.pos += LLSDNOTATIONSENTINEL.len();
+        }
+        Lexer {
+            cursor,
+            start: 0,
+            failed: false,
+        }
+    }
+
+    /// Byte offset in the input of the token most recently returned.
+    pub fn offset(&self) -> usize {
+        self.start
+    }
+
+    /// Next token, or None at end of input.
+    pub fn next_token(&mut self) -> Result<Option<Token>, Error> {
+        self.cursor.skip_whitespace();
+        self.start = self.cursor.pos;
+        if self.cursor.peek().is_none() {
+            return Ok(None);
+        }
+        lex_token(&mut self.cursor).map(Some)
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.next_token();
+        self.failed = result.is_err();
+        result.transpose()
+    }
+}
+
+/// Read position in the input. Notation needs one byte of lookahead.
+struct NotationCursor<'a> {
+    b: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> NotationCursor<'a> {
+    /// Unread part of input.
+    fn rest(&self) -> &'a [u8] {
+        &self.b[self.pos..]
+    }
+
+    /// Next byte, not consumed.
+    fn peek(&self) -> Option<u8> {
+        self.b.get(self.pos).copied()
+    }
+
+    /// Next byte, consumed. EOF is an error.
+    fn next_byte(&mut self) -> Result<u8, Error> {
+        match self.peek() {
+            Some(ch) => {
+                self.pos += 1;
+                Ok(ch)
+            }
+            None => Err(llsd_error!(
+                Truncated,
+                "Unexpected end of data at position {}",
+                self.pos
+            )),
+        }
+    }
+
+    /// Consume the given byte, or fail.
+    fn expect(&mut self, expected: u8) -> Result<(), Error> {
+        let pos = self.pos;
+        let ch = self.next_byte()?;
+        if ch != expected {
+            return Err(anyhow!(
+                "Expected '{}', found {:?} at position {}",
+                expected as char,
+                ch as char,
+                pos
+            ));
+        }
+        Ok(())
+    }
+
+    /// Consume exactly N bytes.
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.b.len() - self.pos < n {
+            return Err(llsd_error!(
+                Truncated,
+                "Unexpected end of data at position {}, expected {} more bytes",
+                self.pos,
+                n
+            ));
+        }
+        let out = &self.b[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(out)
+    }
+
+    /// Consume bytes while they match.
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &'a [u8] {
+        let start = self.pos;
+        while matches!(self.peek(), Some(ch) if f(ch)) {
+            self.pos += 1;
+        }
+        &self.b[start..self.pos]
+    }
+
+    /// Whitespace is allowed between all tokens.
+    fn skip_whitespace(&mut self) {
+        let _ = self.take_while(|ch| ch.is_ascii_whitespace());
+    }
+}
+
+/// Parse one value - real, integer, map, etc. Recursive.
+/// `depth` is the number of maps and arrays it is inside.
+fn parse_value(
+    lexer: &mut Lexer,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+    depth: usize,
+) -> Result<LLSDValue, Error> {
+    let token = lexer.next_token()?;
+    parse_value_from(lexer, token, options, diags, depth)
+}
+
+/// Parse one value, given its first token.
+fn parse_value_from(
+    lexer: &mut Lexer,
+    token: Option<Token>,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+    depth: usize,
+) -> Result<LLSDValue, Error> {
+    //  Recursion depth is bounded, so hostile input cannot overflow the stack.
+    if let Some(Token::MapStart) | Some(Token::ArrayStart) = token {
+        options.check_depth(depth + 1, lexer.offset())?;
+    }
+    match token {
+        Some(Token::Undefined) => Ok(LLSDValue::Undefined),
+        Some(Token::Boolean(v)) => Ok(LLSDValue::Boolean(v)),
+        Some(Token::Integer(v)) => Ok(LLSDValue::Integer(v)),
+        Some(Token::Real(v)) => Ok(LLSDValue::Real(v)),
+        Some(Token::UUID(v)) => Ok(LLSDValue::UUID(v)),
+        Some(Token::String(v)) => Ok(LLSDValue::String(v)),
+        Some(Token::URI(v)) => Ok(LLSDValue::URI(v)),
+        Some(Token::Date(v)) => Ok(LLSDValue::Date(v)),
+        Some(Token::Binary(v)) => Ok(LLSDValue::Binary(v)),
+        Some(Token::MapStart) => parse_map(lexer, options, diags, depth + 1),
+        Some(Token::ArrayStart) => parse_array(lexer, options, diags, depth + 1),
+        Some(token) => Err(anyhow!(
+            "Notation LLSD, unexpected {:?} at position {}",
+            token,
+            lexer.offset()
+        )),
+        None => Err(llsd_error!(
+            Truncated,
+            "Unexpected end of data at position {}",
+            lexer.offset()
+        )),
+    }
+}
+
+/// Parse one map. The "{" is already consumed.
+fn parse_map(
+    lexer: &mut Lexer,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+    depth: usize,
+) -> Result<LLSDValue, Error> {
+    let mut map: HashMap<String, LLSDValue> = HashMap::new(); // accumulating map
+    let mut token = lexer.next_token()?;
+    if token == Some(Token::MapEnd) {
+        return Ok(LLSDValue::Map(map)); // empty map
+    }
+    loop {
+        let key = match token {
+            Some(Token::String(key)) => key,
+            _ => {
+                return Err(anyhow!(
+                    "Expected map key, found {:?} at position {}",
+                    token,
+                    lexer.offset()
+                ))
+            }
+        };
+        expect_token(lexer, Token::Colon)?;
+        diags.push_key(&key);
+        let value = parse_value(lexer, options, diags, depth)?;
+        diags.pop();
+        diags.insert(&mut map, key, value);
+        match lexer.next_token()? {
+            Some(Token::Comma) => {}
+            Some(Token::MapEnd) => return Ok(LLSDValue::Map(map)),
+            other => {
+                return Err(anyhow!(
+                    "Expected ',' or '}}' in map, found {:?} at position {}",
+                    other,
+                    lexer.offset()
+                ))
+            }
+        }
+        token = lexer.next_token()?;
+    }
+}
+
+/// Parse one array. The "[" is already consumed.
+fn parse_array(
+    lexer: &mut Lexer,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+    depth: usize,
+) -> Result<LLSDValue, Error> {
+    let mut items: Vec<LLSDValue> = Vec::new(); // accumulate items.
+    let mut token = lexer.next_token()?;
+    if token == Some(Token::ArrayEnd) {
+        return Ok(LLSDValue::Array(items)); // empty array
+    }
+    loop {
+        diags.push_index(items.len());
+        items.push(parse_value_from(lexer, token, options, diags, depth)?);
+        diags.pop();
+        match lexer.next_token()? {
+            Some(Token::Comma) => {}
+            Some(Token::ArrayEnd) => return Ok(LLSDValue::Array(items)),
+            other => {
+                return Err(anyhow!(
+                    "Expected ',' or ']' in array, found {:?} at position {}",
+                    other,
+                    lexer.offset()
+                ))
+            }
+        }
+        token = lexer.next_token()?;
+    }
+}
+
+/// Consume the given token, or fail.
+fn expect_token(lexer: &mut Lexer, expected: Token) -> Result<(), Error> {
+    let token = lexer.next_token()?;
+    if token.as_ref() != Some(&expected) {
+        return Err(anyhow!(
+            "Expected {:?}, found {:?} at position {}",
+            expected,
+            token,
+            lexer.offset()
+        ));
+    }
+    Ok(())
+}
+
+/// Read one token. Whitespace already skipped, and not at end of input.
+fn lex_token(cursor: &mut NotationCursor) -> Result<Token, Error> {
+    let pos = cursor.pos;
+    let typecode = cursor.next_byte()?;
+    match typecode {
+        //  Delimiters
+        b'{' => Ok(Token::MapStart),
+        b'}' => Ok(Token::MapEnd),
+        b'[' => Ok(Token::ArrayStart),
+        b']' => Ok(Token::ArrayEnd),
+        b':' => Ok(Token::Colon),
+        b',' => Ok(Token::Comma),
+        //  Undefined - the empty value
+        b'!' => Ok(Token::Undefined),
+        //  Boolean - 1, 0, t, f, true, false, in any case.
+        b'0' => Ok(Token::Boolean(false)),
+        b'1' => Ok(Token::Boolean(true)),
+        b't' | b'T' | b'f' | b'F' => {
+            let rest = cursor.take_while(|ch| ch.is_ascii_alphabetic());
+            let text = [&[typecode], rest].concat();
+            match parse_bool_text(std::str::from_utf8(&text)?) {
+                Ok(v) => Ok(Token::Boolean(v)),
+                Err(e) => Err(llsd_error!(Value, "{} at position {}", e, pos)),
+            }
+        }
+        //  Integer - i123
+        b'i' => {
+            let text = cursor.take_while(|ch| ch.is_ascii_digit() || ch == b'-' || ch == b'+');
+            match std::str::from_utf8(text)?.parse::<i32>() {
+                Ok(v) => Ok(Token::Integer(v)),
+                Err(e) => Err(llsd_error!(
+                    Value,
+                    "Bad integer at position {}: {:?}",
+                    pos,
+                    e
+                )),
+            }
+        }
+        //  Real - r1.5, r1e10, rnan
+        b'r' => {
+            let text = cursor.take_while(|ch| {
+                ch.is_ascii_alphanumeric() || ch == b'-' || ch == b'+' || ch == b'.'
+            });
+            match std::str::from_utf8(text)?.parse::<f64>() {
+                Ok(v) => Ok(Token::Real(v)),
+                Err(e) => Err(llsd_error!(Value, "Bad real at position {}: {:?}", pos, e)),
+            }
+        }
+        //  UUID - u followed by 36 characters
+        b'u' => {
+            let text = at(
+                std::str::from_utf8(cursor.take(36)?),
+                LLSDErrorKind::Encoding,
+                "UUID",
+                pos,
+            )?;
+            Ok(Token::UUID(at(
+                uuid::Uuid::parse_str(text),
+                LLSDErrorKind::Value,
+                "UUID",
+                pos,
+            )?))
+        }
+        //  String - quoted, or raw with length
+        b'\'' | b'"' => Ok(Token::String(parse_quoted(cursor, typecode)?)),
+        b's' => Ok(Token::String(parse_raw_string(cursor)?)),
+        //  URI - l"http://..."
+        b'l' => {
+            let quote = parse_open_quote(cursor)?;
+            Ok(Token::URI(parse_quoted(cursor, quote)?))
+        }
+        //  Date - d"2006-02-01T14:29:53Z"
+        b'd' => {
+            let quote = parse_open_quote(cursor)?;
+            Ok(Token::Date(at(
+                parse_date(&parse_quoted(cursor, quote)?),
+                LLSDErrorKind::Value,
+                "date",
+                pos,
+            )?))
+        }
+        //  Binary - b64"...", b16"...", or raw b(N)"..."
+        b'b' => Ok(Token::Binary(parse_binary(cursor)?)),
+        _ => Err(anyhow!(
+            "Notation LLSD, unexpected type code {:?} at position {}",
+            typecode as char,
+            pos
+        )),
+    }
+}
+
+/// Add the position to an error from a conversion which does not know it.
+/// Bad text is an Encoding error, bad content a Value error.
+fn at<T, E: std::fmt::Display>(
+    result: Result<T, E>,
+    kind: LLSDErrorKind,
+    what: &str,
+    pos: usize,
+) -> Result<T, Error> {
+    result.map_err(|e| {
+        LLSDError {
+            kind,
+            message: format!("Bad {} at position {}: {}", what, pos, e),
+        }
+        .into()
+    })
+}
+
+/// Either quote character may open a string.
+fn parse_open_quote(cursor: &mut NotationCursor) -> Result<u8, Error> {
+    let pos = cursor.pos;
+    match cursor.next_byte()? {
+        b'\'' => Ok(b'\''),
+        b'"' => Ok(b'"'),
+        ch => Err(anyhow!(
+            "Expected quote, found {:?} at position {}",
+            ch as char,
+            pos
+        )),
+    }
+}
+
+/// Parse a quoted string with backslash escapes. Opening quote already consumed.
+fn parse_quoted(cursor: &mut NotationCursor, quote: u8) -> Result<String, Error> {
+    let start = cursor.pos;
+    let mut s: Vec<u8> = Vec::new();
+    loop {
+        let ch = match cursor.next_byte() {
+            Ok(ch) => ch,
+            Err(_) => {
+                return Err(anyhow!(
+                    "Unterminated string starting at position {}",
+                    start
+                ))
+            }
+        };
+        match ch {
+            _ if ch == quote => break,
+            b'\\' => s.push(parse_escape(cursor)?),
+            _ => s.push(ch),
+        }
+    }
+    at(
+        String::from_utf8(s),
+        LLSDErrorKind::Encoding,
+        "string",
+        start,
+    )
+}
+
+/// Parse one escape sequence. Backslash already consumed.
+fn parse_escape(cursor: &mut NotationCursor) -> Result<u8, Error> {
+    let pos = cursor.pos;
+    Ok(match cursor.next_byte()? {
+        b'a' => 0x07,
+        b'b' => 0x08,
+        b'f' => 0x0c,
+        b'n' => b'\n',
+        b'r' => b'\r',
+        b't' => b'\t',
+        b'v' => 0x0b,
+        b'x' => {
+            let hexdigits = at(
+                std::str::from_utf8(cursor.take(2)?),
+                LLSDErrorKind::Encoding,
+                "\\x escape",
+                pos,
+            )?;
+            match u8::from_str_radix(hexdigits, 16) {
+                Ok(v) => v,
+                Err(_) => return Err(llsd_error!(Encoding, "Bad \\x escape at position {}", pos)),
+            }
+        }
+        ch => ch, // \\, \', \", and anything else stand for themselves
+    })
+}
+
+/// Parse "(N)". Used by the raw string and binary forms.
+fn parse_length(cursor: &mut NotationCursor) -> Result<usize, Error> {
+    cursor.expect(b'(')?;
+    let pos = cursor.pos;
+    let text = cursor.take_while(|ch| ch.is_ascii_digit());
+    let len = match std::str::from_utf8(text)?.parse::<usize>() {
+        Ok(len) => len,
+        Err(e) => return Err(anyhow!("Bad length at position {}: {:?}", pos, e)),
+    };
+    cursor.expect(b')')?;
+    Ok(len)
+}
+
+/// Parse (N) followed by exactly N bytes inside quotes. The bytes are not escaped,
+/// so they may contain anything, including quotes.
+fn parse_raw_bytes<'a>(cursor: &mut NotationCursor<'a>) -> Result<&'a [u8], Error> {
+    let len = parse_length(cursor)?;
+    let quote = parse_open_quote(cursor)?;
+    let bytes = cursor.take(len)?;
+    cursor.expect(quote)?;
+    Ok(bytes)
+}
+
+/// Parse raw binary, b(N)"...". The "b" is already consumed.
+/// Exactly N bytes must be followed by the closing quote.
+fn parse_raw_binary(cursor: &mut NotationCursor) -> Result<Vec<u8>, Error> {
+    let declared = parse_length(cursor)?;
+    let position = cursor.pos;
+    let quote = parse_open_quote(cursor)?;
+    let rest = cursor.rest();
+    if rest.get(declared) != Some(&quote) {
+        //  Measure to the first quote. Binary can contain quotes, so this is
+        //  only a guess at what the writer meant, but N is wrong either way.
+        let actual = rest
+            .iter()
+            .position(|&ch| ch == quote)
+            .unwrap_or(rest.len());
+        return Err(BinaryLengthMismatch {
+            declared,
+            actual,
+            position,
+        }
+        .into());
+    }
+    let bytes = cursor.take(declared)?.to_vec();
+    cursor.expect(quote)?;
+    Ok(bytes)
+}
+
+/// Parse raw string, s(N)"...". The "s" is already consumed.
+fn parse_raw_string(cursor: &mut NotationCursor) -> Result<String, Error> {
+    let pos = cursor.pos;
+    Ok(at(
+        std::str::from_utf8(parse_raw_bytes(cursor)?),
+        LLSDErrorKind::Encoding,
+        "string",
+        pos,
+    )?
+    .to_string())
+}
+
+/// Parse binary. The "b" is already consumed.
+fn parse_binary(cursor: &mut NotationCursor) -> Result<Vec<u8>, Error> {
+    if cursor.peek() == Some(b'(') {
+        return parse_raw_binary(cursor);
+    }
+    let pos = cursor.pos;
+    let base = cursor.take_while(|ch| ch.is_ascii_digit());
+    let quote = parse_open_quote(cursor)?;
+    let text = cursor.take_while(|ch| ch != quote);
+    cursor.expect(quote)?;
+    let text = at(
+        std::str::from_utf8(text),
+        LLSDErrorKind::Encoding,
+        "binary",
+        pos,
+    )?;
+    Ok(match base {
+        b"64" => at(
+            base64::decode(text),
+            LLSDErrorKind::Encoding,
+            "base 64 binary",
+            pos,
+        )?,
+        b"16" => at(
+            hex::decode(text),
+            LLSDErrorKind::Encoding,
+            "base 16 binary",
+            pos,
+        )?,
+        b"85" => match ascii85::decode(text) {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(llsd_error!(
+                    Encoding,
+                    "Bad base 85 binary at position {}: {:?}",
+                    pos,
+                    e
+                ))
+            }
+        },
+        _ => {
+            return Err(anyhow!(
+                "Unknown binary encoding b{} at position {}",
+                String::from_utf8_lossy(base),
+                pos
+            ))
+        }
+    })
+}
+
+/// Outputs an LLSDValue as a string of bytes, in LLSD "notation" format.
+/// Binary is written in the raw b(N)"..." form when that is shorter than
+/// base64, so the output is not necessarily valid UTF-8.
+pub fn to_bytes(val: &LLSDValue) -> Result<Vec<u8>, Error> {
+    let mut s: Vec<u8> = Vec::new();
+    s.write_all(LLSDNOTATIONPREFIX)?; // prefix
+    generate_value(&mut s, val, true, 0, 0)?;
+    s.flush()?;
+    Ok(s)
+}
+
+/// Outputs an LLSDValue as a printable string, in LLSD "notation" format.
+/// Binary is always base64 encoded.
+pub fn to_string(val: &LLSDValue) -> Result<String, Error> {
+    let mut s: Vec<u8> = Vec::new();
+    s.write_all(LLSDNOTATIONPREFIX)?; // prefix
+    generate_value(&mut s, val, false, 0, 0)?;
+    s.flush()?;
+    Ok(String::from_utf8(s)?)
+}
+
+/// Outputs an LLSDValue as a printable string, in LLSD "notation" format,
+/// with each map entry and array element on its own line, indented by
+/// `indent` spaces per level. Scalars and empty maps and arrays stay on one line.
+/// For files people edit. Binary is always base64 encoded.
+pub fn to_string_pretty(val: &LLSDValue, indent: usize) -> Result<String, Error> {
+    let mut s: Vec<u8> = Vec::new();
+    s.write_all(LLSDNOTATIONPREFIX)?; // prefix
+    generate_value(&mut s, val, false, indent.max(1), 0)?;
+    s.flush()?;
+    Ok(String::from_utf8(s)?)
+}
+
+/// Generate one value. Recursive. If `spaces` is nonzero, containers are
+/// written one item per line, and `indent` is the indentation of this value.
+fn generate_value(
+    s: &mut Vec<u8>,
+    val: &LLSDValue,
+    raw_binary: bool,
+    spaces: usize,
+    indent: usize,
+) -> Result<(), Error> {
+    //  Start a new line for an item, if pretty printing.
+    fn new_line(s: &mut Vec<u8>, spaces: usize, indent: usize) -> Result<(), Error> {
+        if spaces > 0 {
+            write!(s, "\n{:1$}", "", indent)?;
+        }
+        Ok(())
+    }
+
+    //  Emit notation for all possible types.
+    match val {
+        LLSDValue::Undefined => s.write_all(b"!")?,
+        LLSDValue::Boolean(v) => s.write_all(if *v { b"true" } else { b"false" })?,
+        LLSDValue::String(v) => write_quoted(s, v, b'\'')?,
+        LLSDValue::URI(v) => {
+            s.write_all(b"l")?;
+            write_quoted(s, v, b'"')?
+        }
+        LLSDValue::Integer(v) => write!(s, "i{}", v)?,
+        //  Use SL "nan", not Rust "NaN"
+        LLSDValue::Real(v) => {
+            if v.is_nan() {
+                s.write_all(b"rnan")?
+            } else {
+                write!(s, "r{}", v)?
+            }
+        }
+        LLSDValue::UUID(v) => write!(s, "u{}", v)?,
+        LLSDValue::Date(v) => write!(s, "d\"{}\"", format_date(*v)?)?,
+        LLSDValue::Binary(v) => {
+            //  Raw form is b(N)"..."; base64 form is b64"...".
+            let rawlen = v.len() + v.len().to_string().len() + 5;
+            let base64len = v.len().div_ceil(3) * 4 + 5;
+            if raw_binary && rawlen < base64len {
+                write!(s, "b({})\"", v.len())?;
+                s.write_all(v)?;
+                s.write_all(b"\"")?
+            } else {
+                write!(s, "b64\"{}\"", base64::encode(v))?
+            }
+        }
+        //  Map is { 'key' : value , ... }
+        LLSDValue::Map(v) => {
+            s.write_all(b"{")?;
+            for (n, (key, value)) in sorted_map_entries(v).into_iter().enumerate() {
+                if n > 0 {
+                    s.write_all(b",")?;
+                }
+                new_line(s, spaces, indent + spaces)?;
+                write_quoted(s, key, b'\'')?;
+                s.write_all(if spaces > 0 { b": " } else { b":" })?;
+                generate_value(s, value, raw_binary, spaces, indent + spaces)?;
+            }
+            if !v.is_empty() {
+                new_line(s, spaces, indent)?;
+            }
+            s.write_all(b"}")?
+        }
+        //  Array is [ value , ... ]
+        LLSDValue::Array(v) => {
+            s.write_all(b"[")?;
+            for (n, value) in v.iter().enumerate() {
+                if n > 0 {
+                    s.write_all(b",")?;
+                }
+                new_line(s, spaces, indent + spaces)?;
+                generate_value(s, value, raw_binary, spaces, indent + spaces)?;
+            }
+            if !v.is_empty() {
+                new_line(s, spaces, indent)?;
+            }
+            s.write_all(b"]")?
+        }
+    };
+    Ok(())
+}
+
+/// Write a quoted string with backslash escapes. Non-ASCII is left as UTF-8.
+fn write_quoted(s: &mut Vec<u8>, text: &str, quote: u8) -> Result<(), Error> {
+    s.write_all(&[quote])?;
+    for &ch in text.as_bytes() {
+        match ch {
+            b'\\' => s.write_all(b"\\\\")?,
+            _ if ch == quote => s.write_all(&[b'\\', ch])?,
+            b'\n' => s.write_all(b"\\n")?,
+            b'\r' => s.write_all(b"\\r")?,
+            b'\t' => s.write_all(b"\\t")?,
+            0..=0x1f | 0x7f => write!(s, "\\x{:02x}", ch)?,
+            _ => s.write_all(&[ch])?,
+        }
+    }
+    s.write_all(&[quote])?;
+    Ok(())
+}
+
+impl LLSDValue {
+    /// Compact notation for a log line, at most `max_len` bytes plus a
+    /// trailing "…" if cut off. Long strings and binary are shortened and
+    /// marked with their length. Not parseable; use `to_string` for that.
+    pub fn to_log_string(&self, max_len: usize) -> String {
+        let mut s: Vec<u8> = Vec::new();
+        generate_log_value(&mut s, self, max_len);
+        let mut out = String::from_utf8_lossy(&s).into_owned();
+        if out.len() > max_len {
+            let mut end = max_len;
+            while !out.is_char_boundary(end) {
+                end -= 1;
+            }
+            out.truncate(end);
+            out.push('…');
+        }
+        out
+    }
+
+    /// Deep copy for a log snapshot, without the cost of copying big blobs.
+    /// Binary longer than `max_binary_len` is replaced by a String such as
+    /// "<binary: 100000 bytes elided>". Everything else is copied as is.
+    pub fn clone_eliding_binary(&self, max_binary_len: usize) -> LLSDValue {
+        match self {
+            LLSDValue::Binary(v) if v.len() > max_binary_len => {
+                LLSDValue::String(format!("<binary: {} bytes elided>", v.len()))
+            }
+            LLSDValue::Map(m) => LLSDValue::Map(
+                m.iter()
+                    .map(|(k, v)| (k.clone(), v.clone_eliding_binary(max_binary_len)))
+                    .collect(),
+            ),
+            LLSDValue::Array(a) => LLSDValue::Array(
+                a.iter()
+                    .map(|v| v.clone_eliding_binary(max_binary_len))
+                    .collect(),
+            ),
+            _ => self.clone(),
+        }
+    }
+}
+
+/// Generate one value for a log line. Recursive. Stops once past the length limit.
+fn generate_log_value(s: &mut Vec<u8>, val: &LLSDValue, max_len: usize) {
+    if s.len() > max_len {
+        return;
+    }
+    match val {
+        LLSDValue::String(v) if v.len() > LOGSTRINGLIMIT => {
+            let mut end = LOGSTRINGLIMIT;
+            while !v.is_char_boundary(end) {
+                end -= 1;
+            }
+            let _ = write_quoted(s, &v[..end], b'\'');
+            let _ = write!(s, "…({} bytes)", v.len());
+        }
+        LLSDValue::Binary(v) if v.len() > LOGBINARYLIMIT => {
+            let _ = write!(
+                s,
+                "b64\"{}\"…({} bytes)",
+                base64::encode(&v[..LOGBINARYLIMIT]),
+                v.len()
+            );
+        }
+        LLSDValue::Map(v) => {
+            s.push(b'{');
+            for (n, (key, value)) in sorted_map_entries(v).into_iter().enumerate() {
+                if s.len() > max_len {
+                    return;
+                }
+                if n > 0 {
+                    s.push(b',');
+                }
+                let _ = write_quoted(s, key, b'\'');
+                s.push(b':');
+                generate_log_value(s, value, max_len);
+            }
+            s.push(b'}');
+        }
+        LLSDValue::Array(v) => {
+            s.push(b'[');
+            for (n, value) in v.iter().enumerate() {
+                if s.len() > max_len {
+                    return;
+                }
+                if n > 0 {
+                    s.push(b',');
+                }
+                generate_log_value(s, value, max_len);
+            }
+            s.push(b']');
+        }
+        _ => {
+            let _ = generate_value(s, val, false, 0, 0); // scalars are short
+        }
+    }
+}
+
+// Unit tests
+
+#[test]
+fn notationparsetest1() {
+    const TESTNOTATION1: &str = r#"
+<? llsd/notation ?>
+[
+  {'region_id':u67153d5b-3659-afb4-8510-adda2c034649,"scale":'one minute'},
+  i42, r-1.5, true, F, !, l"http://example.com/a?b=\"c\"",
+  d"2006-02-01T14:29:53Z", b64"SGVsbG8gd29ybGQ=", b16"0fa1", s(5)"a'b\c",
+  'Tab\there \x41\'',
+  []
+]
+"#;
+    let parsed1 = parse(TESTNOTATION1.as_bytes()).unwrap();
+    println!("Parse of {}: \n{:#?}", TESTNOTATION1, parsed1);
+    let items = parsed1.as_array().unwrap();
+    assert_eq!(13, items.len());
+    assert_eq!(
+        "one minute",
+        items[0]
+            .as_map()
+            .unwrap()
+            .get("scale")
+            .unwrap()
+            .as_string()
+            .unwrap()
+    );
+    assert_eq!(LLSDValue::Boolean(false), items[4]);
+    assert_eq!(
+        LLSDValue::URI("http://example.com/a?b=\"c\"".to_string()),
+        items[6]
+    );
+    assert_eq!(LLSDValue::Binary(b"Hello world".to_vec()), items[8]);
+    assert_eq!(LLSDValue::String("a'b\\c".to_string()), items[10]);
+    assert_eq!(LLSDValue::String("Tab\there A'".to_string()), items[11]);
+    //  Round trip through both output forms.
+    let generated = to_string(&parsed1).unwrap();
+    println!("Generated: {}", generated);
+    assert_eq!(parsed1, parse(generated.as_bytes()).unwrap());
+    assert_eq!(parsed1, parse(&to_bytes(&parsed1).unwrap()).unwrap());
+    //  Errors
+    assert!(parse(b"[i1,i2").is_err());
+    assert!(parse(b"{'a' i1}").is_err());
+    assert!(parse(b"i1 i2").is_err());
+}
+
+#[test]
+fn notationrawbinarytest() {
+    //  Raw binary must be read by length, since it can contain quotes and NULs.
+    let test1 = LLSDValue::Array(vec![
+        LLSDValue::Binary(vec![0x00, 0x22, 0xff, b'A']),
+        LLSDValue::String("after".to_string()),
+    ]);
+    let generated = to_bytes(&test1).unwrap();
+    println!("Generated: {:?}", generated);
+    let expected: &[u8] = b"[b(4)\"\x00\x22\xffA\",'after']";
+    assert!(generated.ends_with(expected));
+    assert_eq!(test1, parse(&generated).unwrap());
+    assert_eq!(test1, LLSDValue::parse(&generated).unwrap());
+    //  Without the header, and not UTF-8.
+    let raw: &[u8] = b"b(2)\"\xff\xfe\"";
+    assert_eq!(LLSDValue::Binary(vec![0xff, 0xfe]), parse(raw).unwrap());
+    assert_eq!(parse(raw).unwrap(), LLSDValue::parse(raw).unwrap());
+    assert_eq!(test1, LLSDValue::parse(expected).unwrap());
+    //  Printable form falls back to base64.
+    let printable = to_string(&test1).unwrap();
+    assert!(printable.contains("b64\"ACL/QQ==\""));
+    assert_eq!(test1, parse(printable.as_bytes()).unwrap());
+}
+
+#[test]
+fn notationrawbinarylengthtest() {
+    //  Correct length
+    assert_eq!(
+        LLSDValue::Binary(b"abc".to_vec()),
+        parse(b"b(3)\"abc\"").unwrap()
+    );
+    //  Too short - declared more than is there.
+    let err = parse(b"[b(5)\"abc\",'x']").unwrap_err();
+    println!("Too short: {}", err);
+    assert_eq!(
+        Some(&BinaryLengthMismatch {
+            declared: 5,
+            actual: 3,
+            position: 5
+        }),
+        err.downcast_ref::<BinaryLengthMismatch>()
+    );
+    let err = parse(b"b(5)\"abc").unwrap_err();
+    assert_eq!(
+        3,
+        err.downcast_ref::<BinaryLengthMismatch>().unwrap().actual
+    );
+    //  Too long - more bytes than declared.
+    let err = parse(b"b(2)\"abcd\"").unwrap_err();
+    println!("Too long: {}", err);
+    let mismatch = err.downcast_ref::<BinaryLengthMismatch>().unwrap();
+    assert_eq!((2, 4), (mismatch.declared, mismatch.actual));
+}
+
+#[test]
+fn notationlexertest() {
+    const TESTNOTATION: &[u8] =
+        b"<? llsd/notation ?>\n{'a b':[i-3, r1.5e2, TRUE, 0], \"q\\\"\":s(3)\"x'y\", 'u':!}";
+    let tokens: Vec<Token> = Lexer::new(TESTNOTATION).map(|t| t.unwrap()).collect();
+    assert_eq!(
+        vec![
+            Token::MapStart,
+            Token::String("a b".to_string()),
+            Token::Colon,
+            Token::ArrayStart,
+            Token::Integer(-3),
+            Token::Comma,
+            Token::Real(150.0),
+            Token::Comma,
+            Token::Boolean(true),
+            Token::Comma,
+            Token::Boolean(false),
+            Token::ArrayEnd,
+            Token::Comma,
+            Token::String("q\"".to_string()),
+            Token::Colon,
+            Token::String("x'y".to_string()),
+            Token::Comma,
+            Token::String("u".to_string()),
+            Token::Colon,
+            Token::Undefined,
+            Token::MapEnd,
+        ],
+        tokens
+    );
+    //  Positions are byte offsets of token starts.
+    let mut lexer = Lexer::new(b"  [ i1 ]");
+    assert_eq!(Some(Token::ArrayStart), lexer.next_token().unwrap());
+    assert_eq!(2, lexer.offset());
+    assert_eq!(Some(Token::Integer(1)), lexer.next_token().unwrap());
+    assert_eq!(4, lexer.offset());
+    //  Iteration stops after an error.
+    let results: Vec<Result<Token, Error>> = Lexer::new(b"[ x i1 ]").collect();
+    assert_eq!(2, results.len());
+    assert!(results[1].is_err());
+}
+
+#[test]
+fn notationlogstringtest() {
+    let big = LLSDValue::Array(
+        (0..1000)
+            .map(|n| {
+                LLSDValue::Map(
+                    [
+                        ("name".to_string(), LLSDValue::String("x".repeat(500))),
+                        ("blob".to_string(), LLSDValue::Binary(vec![0u8; 10000])),
+                        ("n".to_string(), LLSDValue::Integer(n)),
+                    ]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                )
+            })
+            .collect(),
+    );
+    for max_len in &[0, 10, 100, 1000] {
+        let s = big.to_log_string(*max_len);
+        println!("Log string: {}", s);
+        assert!(s.len() <= max_len + '…'.len_utf8());
+        assert!(s.ends_with('…'));
+    }
+    let s = big.to_log_string(1000);
+    assert!(s.contains("…(500 bytes)"));
+    assert!(s.contains("…(10000 bytes)"));
+    //  Small values are complete.
+    let small = LLSDValue::Array(vec![
+        LLSDValue::Integer(1),
+        LLSDValue::String("a".to_string()),
+    ]);
+    assert_eq!("[i1,'a']", small.to_log_string(100));
+}
+
+#[test]
+fn notationwhitespacetest() {
+    //  The notation spec has no comments, but whitespace of any kind may go between tokens.
+    const TESTNOTATION: &str = "<? llsd/notation ?>\r\n\
+{\r\n\
+\t'region_id' \t:\r\n    u67153d5b-3659-afb4-8510-adda2c034649 ,\n\
+    'stats'  :  {\n\
+        'sim fps' : r44.5 ,\n\
+        'agents'\n:\ni3\n\
+    } ,\n\
+    'list' : [ i1 , i2 ,\n  i3 ] ,\n\
+    'empty' : [ ] , 'none' : { \n }\n\
+}\n\n";
+    let parsed = parse(TESTNOTATION.as_bytes()).unwrap();
+    println!("Parsed: {:#?}", parsed);
+    assert_eq!(
+        Some(&LLSDValue::Real(44.5)),
+        parsed.pointer("/stats/sim fps")
+    );
+    assert_eq!(
+        Some(&LLSDValue::Integer(3)),
+        parsed.pointer("/stats/agents")
+    );
+    assert_eq!(Some(&LLSDValue::Integer(3)), parsed.pointer("/list/2"));
+    assert_eq!(
+        Some(&LLSDValue::Array(Vec::new())),
+        parsed.pointer("/empty")
+    );
+    assert_eq!(
+        Some(&LLSDValue::Map(HashMap::new())),
+        parsed.pointer("/none")
+    );
+    //  Comments are not part of notation.
+    assert!(parse(b"[ i1, # comment\n i2 ]").is_err());
+}
+
+#[test]
+fn notationtoplevelscalartest() {
+    //  No value at all
+    for empty in &["", "   \n", "<? llsd/notation ?>\n"] {
+        let err = parse(empty.as_bytes()).unwrap_err();
+        println!("Empty: {}", err);
+        assert!(err.to_string().contains("unexpected end of data"));
+    }
+    //  Lone undefined
+    assert_eq!(LLSDValue::Undefined, parse(b"!").unwrap());
+    assert_eq!(LLSDValue::Undefined, LLSDValue::parse(b"!").unwrap());
+    //  Whitespace around a scalar
+    assert_eq!(LLSDValue::Integer(5), parse(b"  i5  ").unwrap());
+    assert_eq!(LLSDValue::Integer(5), LLSDValue::parse(b"  i5  ").unwrap());
+    assert_eq!(LLSDValue::Undefined, parse(b"\t!\n").unwrap());
+}
+
+#[test]
+fn notationcloneelidingbinarytest() {
+    let val = parse(
+        b"{'texture':b64\"AAECAwQFBgcICQ==\",'icon':b16\"0102\",\
+        'list':[b16\"00112233445566778899\",i1,'text'],'name':'Vallone'}",
+    )
+    .unwrap();
+    let snapshot = val.clone_eliding_binary(4);
+    println!("Snapshot: {:?}", snapshot);
+    assert_eq!(
+        Some(&LLSDValue::String("<binary: 10 bytes elided>".to_string())),
+        snapshot.pointer("/texture")
+    );
+    assert_eq!(
+        Some(&LLSDValue::String("<binary: 10 bytes elided>".to_string())),
+        snapshot.pointer("/list/0")
+    );
+    //  Small binaries and everything else unchanged.
+    assert_eq!(val.pointer("/icon"), snapshot.pointer("/icon"));
+    assert_eq!(val.pointer("/list/1"), snapshot.pointer("/list/1"));
+    assert_eq!(val.pointer("/list/2"), snapshot.pointer("/list/2"));
+    assert_eq!(val.pointer("/name"), snapshot.pointer("/name"));
+    //  A large enough limit copies everything.
+    assert_eq!(val, val.clone_eliding_binary(10));
+}
+
+#[test]
+fn notationquotedkeystest() {
+    //  Keys and strings may be in single or double quotes.
+    let parsed = parse(br#"{'a':i1,"b":i2}"#).unwrap();
+    assert_eq!(Some(&LLSDValue::Integer(1)), parsed.pointer("/a"));
+    assert_eq!(Some(&LLSDValue::Integer(2)), parsed.pointer("/b"));
+    //  Each quote can be escaped inside its own kind, and needs no escape in the other.
+    let parsed = parse(br#"{'it\'s':"say \"hi\"", "it's":'say "hi"', 'tab\t':"\x41\\"}"#).unwrap();
+    println!("Parsed: {:?}", parsed);
+    assert_eq!(
+        Some(&LLSDValue::String("say \"hi\"".to_string())),
+        parsed.pointer("/it's")
+    );
+    assert_eq!(2, parsed.as_map().unwrap().len()); // "it's" twice, last wins
+    assert_eq!(
+        Some(&LLSDValue::String("A\\".to_string())),
+        parsed.pointer("/tab\t")
+    );
+    //  Quotes must match.
+    assert!(parse(br#"{'a":i1}"#).is_err());
+}
+
+#[test]
+fn notationrealformstest() {
+    //  A real needs no decimal point, and is still distinct from an integer.
+    assert_eq!(LLSDValue::Real(5.0), parse(b"r5").unwrap());
+    assert_eq!(LLSDValue::Integer(5), parse(b"i5").unwrap());
+    assert_eq!(LLSDValue::Real(-1.5), parse(b"r-1.5").unwrap());
+    assert_eq!(LLSDValue::Real(1e10), parse(b"r1e10").unwrap());
+    assert_eq!(LLSDValue::Real(-2.5e-3), parse(b"r-2.5E-3").unwrap());
+    assert_eq!(
+        LLSDValue::Array(vec![LLSDValue::Real(5.0), LLSDValue::Integer(5)]),
+        parse(b"[r5,i5]").unwrap()
+    );
+    assert!(parse(b"r").is_err());
+    assert!(parse(b"r1.2.3").is_err());
+}
+
+#[test]
+fn notationerrorpositiontest() {
+    //  Each error must give the offset of the bad item.
+    let cases: [(&[u8], usize); 7] = [
+        (b"{'a':i1,'b':x}", 12),                             // bad type code
+        (b"{'a':i1 'b':i2}", 8),                             // missing comma
+        (b"[i1, u00000000-0000-0000-0000-00000000000z]", 5), // bad UUID
+        (b"[d'2006-02-31T00:00:00Z']", 1),                   // bad date
+        (b"[b64'not base 64!']", 2),                         // bad binary, after the b
+        (b"[b16'abc']", 2),                                  // odd length hex
+        (b"['ab\\xzz']", 5),                                 // bad escape
+    ];
+    for (text, offset) in cases.iter() {
+        let err = parse(text).unwrap_err().to_string();
+        println!("{}: {}", String::from_utf8_lossy(text), err);
+        assert!(
+            err.contains(&format!("at position {}", offset)),
+            "{:?} should report position {}",
+            err,
+            offset
+        );
+    }
+}
+
+#[test]
+fn notationprettytest() {
+    let val =
+        parse(b"{'name':'Vallone','pos':[r1.5,i2,[]],'empty':{},'nested':{'ok':true}}").unwrap();
+    let pretty = to_string_pretty(&val, 4).unwrap();
+    println!("{}", pretty);
+    let expected = "<? llsd/notation ?>
+{
+    'empty': {},
+    'name': 'Vallone',
+    'nested': {
+        'ok': true
+    },
+    'pos': [
+        r1.5,
+        i2,
+        []
+    ]
+}";
+    assert_eq!(expected, pretty);
+    assert_eq!(val, parse(pretty.as_bytes()).unwrap());
+    //  Scalars are the same as compact.
+    let scalar = LLSDValue::Integer(5);
+    assert_eq!(
+        to_string(&scalar).unwrap(),
+        to_string_pretty(&scalar, 2).unwrap()
+    );
+}
+
+#[test]
+fn notationdepthlimittest() {
+    use crate::{NestingTooDeep, DEFAULT_MAX_DEPTH};
+    //  Deep nesting is an error, not a stack overflow.
+    let deep = vec![b'['; 200_000];
+    let err = parse(&deep).unwrap_err();
+    println!("Error as expected: {}", err);
+    assert_eq!(
+        Some(&NestingTooDeep {
+            limit: DEFAULT_MAX_DEPTH,
+            position: DEFAULT_MAX_DEPTH
+        }),
+        err.downcast_ref::<NestingTooDeep>()
+    );
+    assert!(LLSDValue::parse(&deep).is_err());
+    //  Up to the limit is fine, for maps too.
+    let mut ok = "[".repeat(DEFAULT_MAX_DEPTH);
+    ok.push_str(&"]".repeat(DEFAULT_MAX_DEPTH));
+    assert!(parse(ok.as_bytes()).is_ok());
+    let over = format!("[{}]", ok);
+    assert!(parse(over.as_bytes()).is_err());
+    let maps = format!(
+        "{}i1{}",
+        "{'a':".repeat(DEFAULT_MAX_DEPTH + 1),
+        "}".repeat(DEFAULT_MAX_DEPTH + 1)
+    );
+    assert!(parse(maps.as_bytes())
+        .unwrap_err()
+        .downcast_ref::<NestingTooDeep>()
+        .is_some());
+}
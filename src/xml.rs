@@ -10,7 +10,10 @@
 //  February, 2021.
 //  License: LGPL.
 //
-use super::LLSDValue;
+use super::{
+    escape_path_key, parse_bool_text, sorted_map_entries, trace_error, Diagnostic, Diagnostics,
+    Format, LLSDValue, ParseOptions,
+};
 use anyhow::{anyhow, Error};
 use ascii85;
 use base64;
@@ -21,6 +24,7 @@ use quick_xml::events::attributes::Attributes;
 use quick_xml::events::Event;
 use quick_xml::Reader;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::io::Write;
 use uuid;
 //
@@ -30,28 +34,332 @@ pub const LLSDXMLPREFIX: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ll
 pub const LLSDXMLSENTINEL: &str = "<?xml"; // Must begin with this.
 const INDENT: usize = 4; // indent 4 spaces if asked
 
+/// How reals are written in XML.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RealFormat {
+    /// Shortest text which reads back as exactly the same value.
+    Shortest,
+    /// This many digits after the decimal point.
+    Fixed(usize),
+    /// This many significant digits, for compact output.
+    Significant(usize),
+}
+
+/// Line ending for XML output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    /// "\n", as on Unix.
+    Lf,
+    /// "\r\n", for Windows tools which want it.
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// Options for XML output.
+#[derive(Debug, Clone)]
+pub struct XmlOptions {
+    pub real_format: RealFormat,
+    /// Ends each line. Line breaks inside strings are written as they are.
+    pub line_ending: LineEnding,
+    /// Start with a UTF-8 byte order mark.
+    pub emit_bom: bool,
+}
+
+impl Default for XmlOptions {
+    fn default() -> Self {
+        XmlOptions {
+            real_format: RealFormat::Shortest,
+            line_ending: LineEnding::Lf,
+            emit_bom: false,
+        }
+    }
+}
+
+/// A second value inside one `<llsd>` block. `<llsd>` holds exactly one value.
+/// Returned inside an anyhow::Error; use downcast_ref to identify it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultipleRootValues {
+    pub tag: String,     // start tag of the extra value
+    pub position: usize, // just after that tag
+}
+
+impl std::fmt::Display for MultipleRootValues {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "More than one value in <llsd>, found <{}> at position {}",
+            self.tag, self.position
+        )
+    }
+}
+
+impl std::error::Error for MultipleRootValues {}
+
+/// A character which XML 1.0 forbids, such as NUL or most other control characters,
+/// in a string, URI, or map key being written as XML. Binary LLSD and notation
+/// can hold any character; XML cannot, even as a character reference. That
+/// includes vertical tab and form feed at the ends of a string, where other
+/// whitespace is written as a character reference.
+/// Returned inside an anyhow::Error; use downcast_ref to identify it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnrepresentableInXml {
+    pub ch: char,     // the forbidden character
+    pub text: String, // the string containing it
+}
+
+impl std::fmt::Display for UnrepresentableInXml {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Character {:?} cannot be represented in XML, in {:?}",
+            self.ch, self.text
+        )
+    }
+}
+
+impl std::error::Error for UnrepresentableInXml {}
+
+/// A date which cannot be written as RFC 3339 text: not finite, or outside
+/// years 0000 to 9999. Returned inside an anyhow::Error when serializing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidDate {
+    pub value: f64, // seconds since 1970
+}
+
+impl std::fmt::Display for InvalidDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "LLSD date {} is out of range for text formats",
+            self.value
+        )
+    }
+}
+
+impl std::error::Error for InvalidDate {}
+
 ///    Parse LLSD expressed in XML into an LLSD tree.
 pub fn parse(xmlstr: &str) -> Result<LLSDValue, Error> {
+    parse_with_options(xmlstr, &ParseOptions::default())
+}
+
+///    Parse LLSD expressed in XML into an LLSD tree, with options.
+pub fn parse_with_options(xmlstr: &str, options: &ParseOptions) -> Result<LLSDValue, Error> {
+    parse_one(xmlstr, options, &mut Diagnostics::new(false))
+}
+
+///    Parse LLSD expressed in XML, continuing past bad values to report them all.
+///    A real, integer, boolean, UUID, date, or binary whose text cannot be converted
+///    becomes Undefined, with a `Diagnostic::BadValue`. Duplicate keys are reported too.
+///    Errors in the XML structure, such as unknown or unmatched tags, are still
+///    fatal, since the rest of the document cannot be trusted.
+pub fn parse_collecting_errors(xmlstr: &str) -> Result<(LLSDValue, Vec<Diagnostic>), Error> {
+    let mut diags = Diagnostics::collecting_errors();
+    let val = parse_one(xmlstr, &ParseOptions::default(), &mut diags)?;
+    Ok((val, diags.found))
+}
+
+///    Parse LLSD expressed in XML, collecting diagnostics.
+pub(crate) fn parse_diagnosed(
+    xmlstr: &str,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+) -> Result<LLSDValue, Error> {
+    parse_one(xmlstr, options, diags)
+}
+
+///    Parse the one <llsd> block.
+fn parse_one(
+    xmlstr: &str,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+) -> Result<LLSDValue, Error> {
+    match trace_error(Format::Xml, parse_blocks(xmlstr, false, options, diags))?.pop() {
+        Some(out) => Ok(out),
+        None => Err(llsd_error!(
+            Truncated,
+            "Unexpected end of data, no <llsd> block."
+        )),
+    }
+}
+
+///    Parse XML containing several LLSD documents, one after another,
+///    as found in log files. Returns the value of each `<llsd>` block, in order.
+pub fn parse_all(xmlstr: &str) -> Result<Vec<LLSDValue>, Error> {
+    trace_error(
+        Format::Xml,
+        parse_blocks(
+            xmlstr,
+            true,
+            &ParseOptions::default(),
+            &mut Diagnostics::new(false),
+        ),
+    )
+}
+
+/// Original text of reals, by path in the syntax of LLSDValue::pointer.
+pub type RealText = HashMap<String, String>;
+
+///    Parse LLSD expressed in XML, also returning the original text of each real.
+///    Writing the value with `to_xml_string_preserving_reals` then reproduces
+///    reals such as "0.10" exactly, rather than as "0.1", unless they were changed.
+pub fn parse_preserving_reals(xmlstr: &str) -> Result<(LLSDValue, RealText), Error> {
+    let val = parse(xmlstr)?;
+    Ok((val, collect_real_text(xmlstr)?))
+}
+
+///    Find the text of each <real>, and its path. Input has already been parsed
+///    successfully, so this just has to keep track of where it is.
+fn collect_real_text(xmlstr: &str) -> Result<RealText, Error> {
+    enum Frame {
+        Map(String),  // most recent key
+        Array(usize), // index of next item
+    }
+    let mut reader = Reader::from_str(xmlstr);
+    reader.trim_text(true);
+    reader.expand_empty_elements(true);
+    let mut buf = Vec::new();
+    let mut frames: Vec<Frame> = Vec::new(); // open maps and arrays
+    let mut path: Vec<String> = Vec::new(); // escaped path segments of open values
+    let mut texts: Vec<String> = Vec::new(); // text of current key or real
+    let mut real_text = RealText::new();
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) if e.name() == b"llsd" || e.name() == b"key" => texts.clear(),
+            Event::Start(ref e) => {
+                //  Start of a value. Its path segment depends on what holds it.
+                texts.clear();
+                match frames.last_mut() {
+                    Some(Frame::Map(key)) => path.push(escape_path_key(key)),
+                    Some(Frame::Array(n)) => {
+                        path.push(n.to_string());
+                        *n += 1;
+                    }
+                    None => {}
+                }
+                match e.name() {
+                    b"map" => frames.push(Frame::Map(String::new())),
+                    b"array" => frames.push(Frame::Array(0)),
+                    _ => {}
+                }
+            }
+            Event::Text(e) => add_text(&mut texts, e.unescape_and_decode(&reader)?),
+            Event::CData(e) => add_text(&mut texts, e.unescape_and_decode(&reader)?), // quick-xml escapes CDATA content
+            Event::Comment(_) => texts.push(String::new()),
+            Event::End(ref e) => match e.name() {
+                b"llsd" => {}
+                b"key" => {
+                    if let Some(Frame::Map(key)) = frames.last_mut() {
+                        *key = join_text(&texts).trim().to_string();
+                    }
+                }
+                name => {
+                    //  End of a value.
+                    if name == b"map" || name == b"array" {
+                        let _ = frames.pop();
+                    } else if name == b"real" {
+                        let mut pathstr = String::new();
+                        for segment in &path {
+                            pathstr.push('/');
+                            pathstr.push_str(segment);
+                        }
+                        let _ = real_text.insert(pathstr, join_text(&texts).trim().to_string());
+                    }
+                    let _ = path.pop();
+                }
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear()
+    }
+    Ok(real_text)
+}
+
+/// Add text or CDATA to the text of a value. These run together, as in XML;
+/// a comment starts a new piece.
+fn add_text(texts: &mut Vec<String>, text: String) {
+    match texts.last_mut() {
+        Some(last) => last.push_str(&text),
+        None => texts.push(text),
+    }
+}
+
+/// Text of a value, with pieces split by comments joined by a space.
+fn join_text(texts: &[String]) -> String {
+    texts
+        .iter()
+        .filter(|text| !text.is_empty())
+        .map(String::as_str)
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Reader work areas, reused through the recursive descent so that
+/// each element does not allocate its own buffer.
+/// A failed parse just drops the buffers it was using.
+#[derive(Default)]
+struct BufPool {
+    free: Vec<Vec<u8>>, // cleared buffers, ready for use
+}
+
+impl BufPool {
+    fn take(&mut self) -> Vec<u8> {
+        self.free.pop().unwrap_or_default()
+    }
+
+    fn give(&mut self, mut buf: Vec<u8>) {
+        buf.clear();
+        self.free.push(buf);
+    }
+}
+
+///    Parse each `<llsd>` block. Only one is allowed unless requested.
+fn parse_blocks(
+    xmlstr: &str,
+    allow_multiple: bool,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+) -> Result<Vec<LLSDValue>, Error> {
+    options.check_size(xmlstr.len())?;
     let mut reader = Reader::from_str(xmlstr);
     reader.trim_text(true); // do not want trailing blanks
     reader.expand_empty_elements(true); // want end tag events always
-    let mut buf = Vec::new(); // reader work area
-    let mut output: Option<LLSDValue> = None;
+    let mut bufs = BufPool::default();
+    let mut buf = bufs.take(); // reader work area
+    let mut output: Vec<LLSDValue> = Vec::new();
     //  Outer parse. Find <llsd> and parse its interior.
     loop {
         match reader.read_event(&mut buf) {
             Ok(Event::Start(ref e)) => {
                 match e.name() {
                     b"llsd" => {
-                        if output.is_some() {
+                        if !output.is_empty() && !allow_multiple {
                             return Err(anyhow!("More than one <llsd> block in data"));
                         }
-                        let mut buf2 = Vec::new();
+                        let mut buf2 = bufs.take();
                         match reader.read_event(&mut buf2) {
                             Ok(Event::Start(ref e)) => {
                                 let tagname = std::str::from_utf8(e.name())?; // tag name as string to start parse
                                                                               //  This does all the real work.
-                                output = Some(parse_value(&mut reader, tagname, &e.attributes())?);
+                                output.push(parse_value(
+                                    &mut reader,
+                                    tagname,
+                                    &e.attributes(),
+                                    options,
+                                    diags,
+                                    &mut bufs,
+                                    0,
+                                )?);
+                                parse_llsd_end(&mut reader, &mut bufs)?;
                             }
                             _ => {
                                 return Err(anyhow!(
@@ -61,6 +369,7 @@ pub fn parse(xmlstr: &str) -> Result<LLSDValue, Error> {
                                 ))
                             }
                         };
+                        bufs.give(buf2);
                     }
                     _ => {
                         return Err(anyhow!(
@@ -87,25 +396,139 @@ pub fn parse(xmlstr: &str) -> Result<LLSDValue, Error> {
         // if we don't keep a borrow elsewhere, we can clear the buffer to keep memory usage low
         buf.clear()
     }
-    //  Final result, if stored
-    match output {
-        Some(out) => Ok(out),
-        None => Err(anyhow!("Unexpected end of data, no <llsd> block.")),
+    Ok(output)
+}
+
+///    After the one value inside <llsd>, the next element must be </llsd>.
+///    Comments and stray text are skipped. A truncated document must not
+///    be accepted just because its value was complete.
+fn parse_llsd_end(reader: &mut Reader<&[u8]>, bufs: &mut BufPool) -> Result<(), Error> {
+    let mut buf = bufs.take();
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::End(ref e)) if e.name() == b"llsd" => break,
+            Ok(Event::Start(ref e)) => {
+                return Err(MultipleRootValues {
+                    tag: String::from_utf8_lossy(e.name()).to_string(),
+                    position: reader.buffer_position(),
+                }
+                .into())
+            }
+            Ok(Event::Eof) => {
+                return Err(llsd_error!(
+                    Truncated,
+                    "Unexpected end of data at position {}, missing </llsd>",
+                    reader.buffer_position()
+                ))
+            }
+            Err(e) => {
+                return Err(anyhow!(
+                    "Error at position {}: {:?}",
+                    reader.buffer_position(),
+                    e
+                ))
+            }
+            _ => (), // comments, text, etc.
+        }
+        buf.clear();
+    }
+    bufs.give(buf);
+    Ok(())
+}
+
+///    True if `xmlstr` is a well-formed XML LLSD document, as `parse` would accept.
+///    This runs the parser and discards the result.
+pub fn is_valid(xmlstr: &str) -> bool {
+    parse(xmlstr).is_ok()
+}
+
+///    Report the LLSD type name of the top-level value, such as "map" or "array",
+///    without parsing the rest. This is the first element inside `<llsd>`.
+pub fn peek_type(xmlstr: &str) -> Result<&'static str, Error> {
+    const LLSDTYPES: [&str; 11] = [
+        "undef", "boolean", "integer", "real", "string", "uri", "uuid", "date", "binary", "map",
+        "array",
+    ];
+    let mut reader = Reader::from_str(xmlstr);
+    reader.trim_text(true); // do not want trailing blanks
+    reader.expand_empty_elements(true); // want end tag events always
+    let mut buf = Vec::new(); // reader work area
+    let mut in_llsd = false;
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => {
+                if !in_llsd {
+                    if e.name() != b"llsd" {
+                        return Err(anyhow!(
+                            "Expected <llsd>, found {:?} error at position {}",
+                            e.name(),
+                            reader.buffer_position()
+                        ));
+                    }
+                    in_llsd = true;
+                } else {
+                    let tagname = match std::str::from_utf8(e.name())? {
+                        "bool" => "boolean", // legacy spelling
+                        tagname => tagname,
+                    };
+                    return match LLSDTYPES.iter().find(|t| **t == tagname) {
+                        Some(t) => Ok(t),
+                        None => Err(anyhow!(
+                            "Unknown data type <{}> at position {}",
+                            tagname,
+                            reader.buffer_position()
+                        )),
+                    };
+                }
+            }
+            Ok(Event::Eof) => {
+                return Err(llsd_error!(
+                    Truncated,
+                    "Unexpected end of data, no LLSD value."
+                ))
+            }
+            Err(e) => {
+                return Err(anyhow!(
+                    "Error at position {}: {:?}",
+                    reader.buffer_position(),
+                    e
+                ))
+            }
+            _ => (), // Text, comments, and the XML header are not of interest
+        }
+        buf.clear()
     }
 }
 
 /// Parse one value - real, integer, map, etc. Recursive.
+/// `depth` is the number of maps and arrays it is inside.
 fn parse_value(
     reader: &mut Reader<&[u8]>,
     starttag: &str,
     attrs: &Attributes,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+    bufs: &mut BufPool,
+    depth: usize,
 ) -> Result<LLSDValue, Error> {
     //  Entered with a start tag alread parsed and in starttag
+    llsd_trace!(
+        position = reader.buffer_position(),
+        tag = starttag,
+        "XML LLSD value"
+    );
     match starttag {
-        "undef" | "real" | "integer" | "boolean" | "string" | "uri" | "binary" | "uuid"
-        | "date" => parse_primitive_value(reader, starttag, attrs),
-        "map" => parse_map(reader),
-        "array" => parse_array(reader),
+        "undef" | "real" | "integer" | "boolean" | "bool" | "string" | "uri" | "binary"
+        | "uuid" | "date" => parse_primitive_value(reader, starttag, attrs, options, diags, bufs),
+        "map" | "array" => {
+            //  Recursion depth is bounded, so hostile input cannot overflow the stack.
+            options.check_depth(depth + 1, reader.buffer_position())?;
+            if starttag == "map" {
+                parse_map(reader, options, diags, bufs, depth + 1)
+            } else {
+                parse_array(reader, options, diags, bufs, depth + 1)
+            }
+        }
         _ => Err(anyhow!(
             "Unknown data type <{}> at position {}",
             starttag,
@@ -119,14 +542,18 @@ fn parse_primitive_value(
     reader: &mut Reader<&[u8]>,
     starttag: &str,
     attrs: &Attributes,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+    bufs: &mut BufPool,
 ) -> Result<LLSDValue, Error> {
     //  Entered with a start tag already parsed and in starttag
     let mut texts = Vec::new(); // accumulate text here
-    let mut buf = Vec::new();
+    let mut buf = bufs.take();
     loop {
         let event = reader.read_event(&mut buf);
         match event {
-            Ok(Event::Text(e)) => texts.push(e.unescape_and_decode(&reader)?),
+            Ok(Event::Text(e)) => add_text(&mut texts, e.unescape_and_decode(reader)?),
+            Ok(Event::CData(e)) => add_text(&mut texts, e.unescape_and_decode(reader)?), // quick-xml escapes CDATA content
             Ok(Event::End(ref e)) => {
                 let tagname = std::str::from_utf8(e.name())?; // tag name as string
                 if starttag != tagname {
@@ -136,46 +563,42 @@ fn parse_primitive_value(
                         tagname
                     ));
                 };
+                bufs.give(buf);
                 //  End of an XML tag. Value is in text.
-                let text = texts.join(" ").trim().to_string(); // combine into one big string
+                //  Strings keep whitespace which was written as character references.
+                let joined = join_text(&texts); // combine into one big string
+                let text = joined.trim().to_string();
                 texts.clear();
                 //  Parse the primitive types.
-                return match starttag {
+                let value = match starttag {
                     "undef" => Ok(LLSDValue::Undefined),
-                    "real" => Ok(LLSDValue::Real(
-                        if text.to_lowercase() == "nan" {
-                            "NaN".to_string()
-                        } else {
-                            text
-                        }
-                        .parse::<f64>()?,
-                    )),
-                    "integer" => Ok(LLSDValue::Integer(text.parse::<i32>()?)),
-                    "boolean" => Ok(LLSDValue::Boolean(parse_boolean(&text)?)),
-                    "string" => Ok(LLSDValue::String(text.to_string())),
-                    "uri" => Ok(LLSDValue::String(text.to_string())),
-                    "uuid" => Ok(LLSDValue::UUID(if text.is_empty() {
-                        uuid::Uuid::nil()
-                    } else {
-                        uuid::Uuid::parse_str(&text)?
-                    })),
-                    "date" => Ok(LLSDValue::Date(parse_date(&text)?)),
-                    "binary" => Ok(LLSDValue::Binary(parse_binary(&text, attrs)?)),
+                    "real" => parse_real(&text, options).map(LLSDValue::Real),
+                    "integer" => parse_integer(&text, options).map(LLSDValue::Integer),
+                    "boolean" | "bool" => parse_bool_text(&text).map(LLSDValue::Boolean),
+                    "string" => Ok(LLSDValue::String(joined)),
+                    "uri" => Ok(LLSDValue::URI(joined)),
+                    "uuid" if text.is_empty() => Ok(LLSDValue::UUID(uuid::Uuid::nil())),
+                    "uuid" => uuid::Uuid::parse_str(&text)
+                        .map(LLSDValue::UUID)
+                        .map_err(Error::from),
+                    "date" => parse_date(&text).map(LLSDValue::Date),
+                    "binary" => parse_binary(&text, attrs).map(LLSDValue::Binary),
                     _ => Err(anyhow!(
                         "Unexpected primitive data type <{}> at position {}",
                         starttag,
                         reader.buffer_position()
                     )),
                 };
-                // unreachable
+                return diags.recover(value); // bad values may be recoverable
             }
             Ok(Event::Eof) => {
-                return Err(anyhow!(
+                return Err(llsd_error!(
+                    Truncated,
                     "Unexpected end of data in primitive value at position {}",
                     reader.buffer_position()
                 ))
             }
-            Ok(Event::Comment(_)) => {} // ignore comment
+            Ok(Event::Comment(_)) => texts.push(String::new()), // comment separates text
             Err(e) => {
                 return Err(anyhow!(
                     "Parse Error at position {}: {:?}",
@@ -192,15 +615,22 @@ fn parse_primitive_value(
                 ))
             }
         }
+        buf.clear();
     }
 }
 
 //  Parse one map.
-fn parse_map(reader: &mut Reader<&[u8]>) -> Result<LLSDValue, Error> {
+fn parse_map(
+    reader: &mut Reader<&[u8]>,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+    bufs: &mut BufPool,
+    depth: usize,
+) -> Result<LLSDValue, Error> {
     //  Entered with a "map" start tag just parsed.
     let mut map: HashMap<String, LLSDValue> = HashMap::new(); // accumulating map
     let mut texts = Vec::new(); // accumulate text here
-    let mut buf = Vec::new();
+    let mut buf = bufs.take();
     loop {
         let event = reader.read_event(&mut buf);
         match event {
@@ -208,26 +638,26 @@ fn parse_map(reader: &mut Reader<&[u8]>) -> Result<LLSDValue, Error> {
                 let tagname = std::str::from_utf8(e.name())?; // tag name as string
                 match tagname {
                     "key" => {
-                        let (k, v) = parse_map_entry(reader)?; // read one key/value pair
-                        let _dup = map.insert(k, v); // insert into map
-                                                     //  Duplicates are not errors, per LLSD spec.
+                        let (k, v) = parse_map_entry(reader, options, diags, bufs, depth)?; // read one key/value pair
+                        diags.insert(&mut map, k, v); // insert into map, allowing dups
                     }
                     _ => {
                         return Err(anyhow!("Expected 'key' in map, found '{}'", tagname));
                     }
                 }
             }
-            Ok(Event::Text(e)) => texts.push(e.unescape_and_decode(&reader)?),
+            Ok(Event::Text(e)) => texts.push(e.unescape_and_decode(reader)?),
             Ok(Event::End(ref e)) => {
                 //  End of an XML tag. No text expected.
                 let tagname = std::str::from_utf8(e.name())?; // tag name as string
                 if "map" != tagname {
                     return Err(anyhow!("Unmatched XML tags: <{}> .. <{}>", "map", tagname));
                 };
-                return Ok(LLSDValue::Map(map)); // done, valid result
+                break; // done, valid result
             }
             Ok(Event::Eof) => {
-                return Err(anyhow!(
+                return Err(llsd_error!(
+                    Truncated,
                     "Unexpected end of data in map at position {}",
                     reader.buffer_position()
                 ))
@@ -248,15 +678,24 @@ fn parse_map(reader: &mut Reader<&[u8]>) -> Result<LLSDValue, Error> {
                 ))
             }
         }
+        buf.clear();
     }
+    bufs.give(buf);
+    Ok(LLSDValue::Map(map))
 }
 
 //  Parse one map entry.
 //  Format <key> STRING </key> LLSDVALUE
-fn parse_map_entry(reader: &mut Reader<&[u8]>) -> Result<(String, LLSDValue), Error> {
+fn parse_map_entry(
+    reader: &mut Reader<&[u8]>,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+    bufs: &mut BufPool,
+    depth: usize,
+) -> Result<(String, LLSDValue), Error> {
     //  Entered with a "key" start tag just parsed.  Expecting text.
     let mut texts = Vec::new(); // accumulate text here
-    let mut buf = Vec::new();
+    let mut buf = bufs.take();
     loop {
         let event = reader.read_event(&mut buf);
         match event {
@@ -264,37 +703,31 @@ fn parse_map_entry(reader: &mut Reader<&[u8]>) -> Result<(String, LLSDValue), Er
                 let tagname = std::str::from_utf8(e.name())?; // tag name as string
                 return Err(anyhow!("Expected 'key' in map, found '{}'", tagname));
             }
-            Ok(Event::Text(e)) => texts.push(e.unescape_and_decode(&reader)?),
+            Ok(Event::Text(e)) => add_text(&mut texts, e.unescape_and_decode(reader)?),
+            Ok(Event::CData(e)) => add_text(&mut texts, e.unescape_and_decode(reader)?), // quick-xml escapes CDATA content
             Ok(Event::End(ref e)) => {
                 //  End of an XML tag. Should be </key>
                 let tagname = std::str::from_utf8(e.name())?; // tag name as string
                 if "key" != tagname {
                     return Err(anyhow!("Unmatched XML tags: <{}> .. <{}>", "key", tagname));
                 };
-                let mut buf = Vec::new();
-                let k = texts.join(" ").trim().to_string(); // the key
+                let k = join_text(&texts); // the key. <key></key> is "", which is valid.
                 texts.clear();
-                match reader.read_event(&mut buf) {
-                    Ok(Event::Start(ref e)) => {
-                        let tagname = std::str::from_utf8(e.name())?; // tag name as string
-                        let v = parse_value(reader, tagname, &e.attributes())?; // parse next value
-                        return Ok((k, v)); // return key value pair
-                    }
-                    _ => {
-                        return Err(anyhow!(
-                            "Unexpected parse error at position {} while parsing map entry",
-                            reader.buffer_position()
-                        ))
-                    }
-                };
+                bufs.give(buf);
+                llsd_trace!(key = %k, "XML LLSD map entry");
+                diags.push_key(&k);
+                let v = parse_map_entry_value(reader, &k, options, diags, bufs, depth)?;
+                diags.pop();
+                return Ok((k, v)); // return key value pair
             }
             Ok(Event::Eof) => {
-                return Err(anyhow!(
+                return Err(llsd_error!(
+                    Truncated,
                     "Unexpected end of data at position {}",
                     reader.buffer_position()
                 ))
             }
-            Ok(Event::Comment(_)) => {} // ignore comment
+            Ok(Event::Comment(_)) => texts.push(String::new()), // comment separates text
             Err(e) => {
                 return Err(anyhow!(
                     "Parse Error at position {}: {:?}",
@@ -310,14 +743,87 @@ fn parse_map_entry(reader: &mut Reader<&[u8]>) -> Result<(String, LLSDValue), Er
                 ))
             }
         }
+        buf.clear();
     }
 }
 
 /// Parse one LLSD object. Recursive.
-fn parse_array(reader: &mut Reader<&[u8]>) -> Result<LLSDValue, Error> {
+/// Parse the value element which follows a map key.
+/// Comments and whitespace between the key and the value are skipped.
+fn parse_map_entry_value(
+    reader: &mut Reader<&[u8]>,
+    key: &str,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+    bufs: &mut BufPool,
+    depth: usize,
+) -> Result<LLSDValue, Error> {
+    let mut buf = bufs.take();
+    loop {
+        let event = reader.read_event(&mut buf);
+        match event {
+            Ok(Event::Start(ref e)) => {
+                let tagname = std::str::from_utf8(e.name())?; // tag name as string
+                let value = parse_value(
+                    reader,
+                    tagname,
+                    &e.attributes(),
+                    options,
+                    diags,
+                    bufs,
+                    depth,
+                )?; // parse next value
+                bufs.give(buf);
+                return Ok(value);
+            }
+            Ok(Event::Text(ref e)) if e.iter().all(|b| b.is_ascii_whitespace()) => {} // ignore whitespace
+            Ok(Event::Comment(_)) => {} // ignore comment
+            Ok(Event::End(ref e)) => {
+                return Err(anyhow!(
+                    "Map key {:?} has no value, found </{}> at position {}",
+                    key,
+                    String::from_utf8_lossy(e.name()),
+                    reader.buffer_position()
+                ))
+            }
+            Ok(Event::Eof) => {
+                return Err(llsd_error!(
+                    Truncated,
+                    "Map key {:?} has no value, unexpected end of data at position {}",
+                    key,
+                    reader.buffer_position()
+                ))
+            }
+            Err(e) => {
+                return Err(anyhow!(
+                    "Parse Error at position {}: {:?}",
+                    reader.buffer_position(),
+                    e
+                ))
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Unexpected parse event {:?} at position {} after key {:?}",
+                    event,
+                    reader.buffer_position(),
+                    key
+                ))
+            }
+        }
+        buf.clear();
+    }
+}
+
+fn parse_array(
+    reader: &mut Reader<&[u8]>,
+    options: &ParseOptions,
+    diags: &mut Diagnostics,
+    bufs: &mut BufPool,
+    depth: usize,
+) -> Result<LLSDValue, Error> {
     //  Entered with an <array> tag just parsed.
     let mut texts = Vec::new(); // accumulate text here
-    let mut buf = Vec::new();
+    let mut buf = bufs.take();
     let mut items: Vec<LLSDValue> = Vec::new(); // accumulate items.
     loop {
         let event = reader.read_event(&mut buf);
@@ -325,9 +831,19 @@ fn parse_array(reader: &mut Reader<&[u8]>) -> Result<LLSDValue, Error> {
             Ok(Event::Start(ref e)) => {
                 let tagname = std::str::from_utf8(e.name())?; // tag name as string
                                                               //  Parse one data item.
-                items.push(parse_value(reader, tagname, &e.attributes())?);
+                diags.push_index(items.len());
+                items.push(parse_value(
+                    reader,
+                    tagname,
+                    &e.attributes(),
+                    options,
+                    diags,
+                    bufs,
+                    depth,
+                )?);
+                diags.pop();
             }
-            Ok(Event::Text(e)) => texts.push(e.unescape_and_decode(&reader)?),
+            Ok(Event::Text(e)) => texts.push(e.unescape_and_decode(reader)?),
             Ok(Event::End(ref e)) => {
                 //  End of an XML tag. Should be </array>
                 let tagname = std::str::from_utf8(e.name())?; // tag name as string
@@ -341,7 +857,8 @@ fn parse_array(reader: &mut Reader<&[u8]>) -> Result<LLSDValue, Error> {
                 break; // end of array
             }
             Ok(Event::Eof) => {
-                return Err(anyhow!(
+                return Err(llsd_error!(
+                    Truncated,
                     "Unexpected end of data at position {}",
                     reader.buffer_position()
                 ))
@@ -362,7 +879,9 @@ fn parse_array(reader: &mut Reader<&[u8]>) -> Result<LLSDValue, Error> {
                 ))
             }
         }
+        buf.clear();
     }
+    bufs.give(buf);
     Ok(LLSDValue::Array(items)) // result is array of items
 }
 
@@ -378,9 +897,15 @@ fn parse_binary(s: &str, attrs: &Attributes) -> Result<Vec<u8>, Error> {
     Ok(match encoding.as_str() {
         "base64" => base64::decode(s)?,
         "base16" => hex::decode(s)?,
-        "base85" => match ascii85::decode(s) {
+        //  ASCII85 ignores all whitespace. Remove it here, since the text may
+        //  have been split across lines and rejoined with spaces.
+        "base85" => match ascii85::decode(
+            &s.chars()
+                .filter(|ch| !ch.is_whitespace())
+                .collect::<String>(),
+        ) {
             Ok(v) => v,
-            Err(e) => return Err(anyhow!("Base 85 decode error: {:?}", e)),
+            Err(e) => return Err(llsd_error!(Encoding, "Base 85 decode error: {:?}", e)),
         },
         _ => {
             return Err(anyhow!(
@@ -391,22 +916,128 @@ fn parse_binary(s: &str, attrs: &Attributes) -> Result<Vec<u8>, Error> {
     })
 }
 
-/// Parse ISO 9660 date, simple form.
-fn parse_date(s: &str) -> Result<i64, Error> {
-    Ok(chrono::DateTime::parse_from_rfc3339(s)?.timestamp())
+/// Parse integer. Strict parsing is decimal, with optional sign, per the spec.
+/// Lenient parsing also accepts "0x" hex and "0b" binary forms.
+fn parse_integer(s: &str, options: &ParseOptions) -> Result<i32, Error> {
+    let err = match s.parse::<i32>() {
+        Ok(v) => return Ok(v),
+        Err(e) => e,
+    };
+    if !options.lenient {
+        return Err(err.into());
+    }
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let v = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = digits
+        .strip_prefix("0b")
+        .or_else(|| digits.strip_prefix("0B"))
+    {
+        i64::from_str_radix(bin, 2)
+    } else {
+        return Err(err.into());
+    }?;
+    Ok(i32::try_from(if negative { -v } else { v })?)
+}
+
+/// Parse real. Accepts SL "nan".
+/// In lenient mode, a single decimal comma, as in "1,5", is taken as a decimal point.
+/// That is not valid LLSD, but some hand-edited files have it.
+fn parse_real(s: &str, options: &ParseOptions) -> Result<f64, Error> {
+    let err = match (if s.to_lowercase() == "nan" { "NaN" } else { s }).parse::<f64>() {
+        Ok(v) => return Ok(v),
+        Err(e) => e,
+    };
+    if options.lenient && s.matches(',').count() == 1 && !s.contains('.') {
+        if let Ok(v) = s.replace(',', ".").parse::<f64>() {
+            return Ok(v);
+        }
+    }
+    Err(llsd_error!(Value, "Bad real {:?}: {}", s, err))
+}
+
+/// Parse ISO 9660 date, simple form. Result is seconds since epoch, with fraction.
+pub(crate) fn parse_date(s: &str) -> Result<f64, Error> {
+    let dt = chrono::DateTime::parse_from_rfc3339(s)?;
+    Ok(dt.timestamp() as f64 + f64::from(dt.timestamp_subsec_nanos()) / 1.0e9)
 }
 
-//  Parse boolean. LSL allows 0. 0.0, false, 1. 1.0, true.
-fn parse_boolean(s: &str) -> Result<bool, Error> {
-    Ok(match s {
-        "0" | "0.0" => false,
-        "1" | "1.0" => true,
-        _ => s.parse::<bool>()?,
+/// Format ISO 9660 date, simple form.
+/// Fractional seconds are rounded to milliseconds, and omitted if zero.
+/// Negative values are dates before 1970. Out of range dates are an InvalidDate error.
+pub(crate) fn format_date(v: f64) -> Result<String, Error> {
+    let dt = date_to_utc(v).ok_or(InvalidDate { value: v })?;
+    Ok(if dt.timestamp_subsec_millis() == 0 {
+        dt.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    } else {
+        dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
     })
 }
 
+/// True if format_date can represent the date.
+pub(crate) fn date_in_range(v: f64) -> bool {
+    date_to_utc(v).is_some()
+}
+
+/// Date as chrono UTC, rounded to milliseconds.
+/// None unless the year has the four digits RFC 3339 requires, 0000 to 9999.
+fn date_to_utc(v: f64) -> Option<chrono::DateTime<chrono::Utc>> {
+    use chrono::Datelike;
+    if !v.is_finite() {
+        return None;
+    }
+    //  Saturates for huge values, which are then out of range below.
+    let millis = (v * 1000.0).round() as i64;
+    let dt = chrono::Utc
+        .timestamp_opt(
+            millis.div_euclid(1000),
+            (millis.rem_euclid(1000) * 1_000_000) as u32,
+        )
+        .single()?;
+    if (0..=9999).contains(&dt.year()) {
+        Some(dt)
+    } else {
+        None
+    }
+}
+
+/// Text form of a real. Use SL "nan", not Rust "NaN".
+pub(crate) fn f64_to_xml(v: f64) -> String {
+    let ss = v.to_string();
+    if ss == "NaN" {
+        "nan".to_string()
+    } else {
+        ss
+    }
+}
+
+/// Text form of a real, as requested. NaN and infinities are always as f64_to_xml.
+fn format_real(v: f64, real_format: RealFormat) -> String {
+    if !v.is_finite() {
+        return f64_to_xml(v);
+    }
+    match real_format {
+        RealFormat::Shortest => f64_to_xml(v),
+        RealFormat::Fixed(digits) => format!("{:.*}", digits, v),
+        RealFormat::Significant(digits) => {
+            //  Round in exponential form, then write the rounded value plainly.
+            let rounded = format!("{:.*e}", digits.max(1) - 1, v);
+            match rounded.parse::<f64>() {
+                Ok(r) => f64_to_xml(r),
+                Err(_) => f64_to_xml(v),
+            }
+        }
+    }
+}
+
 /// Search for attribute in attribute list
-fn get_attr<'a>(attrs: &'a Attributes, key: &[u8]) -> Result<Option<String>, Error> {
+fn get_attr(attrs: &Attributes, key: &[u8]) -> Result<Option<String>, Error> {
     //  Each step has a possible error, so it's hard to do this more cleanly.
     for attr in attrs.clone() {
         let a = attr?;
@@ -422,96 +1053,216 @@ fn get_attr<'a>(attrs: &'a Attributes, key: &[u8]) -> Result<Option<String>, Err
 
 /// Pretty prints out the value as XML. Indents by 4 spaces if requested.
 pub fn to_xml_string(val: &LLSDValue, do_indent: bool) -> Result<String, Error> {
+    to_xml_string_with_options(val, do_indent, &XmlOptions::default())
+}
+
+/// Outputs an LLSDValue as a string of XML, with options.
+pub fn to_xml_string_with_options(
+    val: &LLSDValue,
+    do_indent: bool,
+    options: &XmlOptions,
+) -> Result<String, Error> {
+    let mut s: Vec<u8> = Vec::new();
+    if options.emit_bom {
+        write!(s, "\u{FEFF}")?;
+    }
+    write!(
+        s,
+        "{}",
+        LLSDXMLPREFIX.replace('\n', options.line_ending.as_str())
+    )?; // Standard XML prefix
+    generate_value(
+        &mut s,
+        val,
+        if do_indent { INDENT } else { 0 },
+        0,
+        options,
+        None,
+        "",
+    )?;
+    write!(s, "</llsd>")?;
+    s.flush()?;
+    Ok(std::str::from_utf8(&s)?.to_string())
+}
+
+/// Outputs an LLSDValue as a string of XML, writing reals with their original
+/// text from `parse_preserving_reals` where their values have not changed.
+pub fn to_xml_string_preserving_reals(
+    val: &LLSDValue,
+    do_indent: bool,
+    real_text: &RealText,
+) -> Result<String, Error> {
     let mut s: Vec<u8> = Vec::new();
     write!(s, "{}", LLSDXMLPREFIX)?; // Standard XML prefix
-    generate_value(&mut s, val, if do_indent { INDENT } else { 0 }, 0);
+    generate_value(
+        &mut s,
+        val,
+        if do_indent { INDENT } else { 0 },
+        0,
+        &XmlOptions::default(),
+        Some(real_text),
+        "",
+    )?;
     write!(s, "</llsd>")?;
     s.flush()?;
     Ok(std::str::from_utf8(&s)?.to_string())
 }
 
 /// Generate one <TYPE> VALUE </TYPE> output. VALUE is recursive.
-fn generate_value(s: &mut Vec<u8>, val: &LLSDValue, spaces: usize, indent: usize) {
+/// The path is only tracked when there is original real text to look up.
+fn generate_value(
+    s: &mut Vec<u8>,
+    val: &LLSDValue,
+    spaces: usize,
+    indent: usize,
+    options: &XmlOptions,
+    real_text: Option<&RealText>,
+    path: &str,
+) -> Result<(), Error> {
     //  Output a single tag
-    fn tag(s: &mut Vec<u8>, tag: &str, close: bool, indent: usize) {
+    fn tag(s: &mut Vec<u8>, tag: &str, close: bool, indent: usize, eol: &str) -> Result<(), Error> {
         if indent > 0 {
-            let _ = write!(*s, "{:1$}", " ", indent);
+            write!(*s, "{:1$}", " ", indent)?;
         };
-        let _ = write!(*s, "<{}{}>\n", if close { "/" } else { "" }, tag);
+        write!(*s, "<{}{}>{}", if close { "/" } else { "" }, tag, eol)?;
+        Ok(())
     }
 
     //  Internal fn - write out one tag with a value.
-    fn tag_value(s: &mut Vec<u8>, tag: &str, text: &str, indent: usize) {
+    fn tag_value(
+        s: &mut Vec<u8>,
+        tag: &str,
+        text: &str,
+        indent: usize,
+        eol: &str,
+    ) -> Result<(), Error> {
         if indent > 0 {
-            let _ = write!(*s, "{:1$}", " ", indent);
+            write!(*s, "{:1$}", " ", indent)?;
         };
         if text.is_empty() {
             // if empty, write as null tag
-            let _ = write!(*s, "<{} />\n", tag);
+            write!(*s, "<{} />{}", tag, eol)?;
         } else {
-            let _ = write!(*s, "<{}>{}</{}>\n", tag, xml_escape(text), tag);
+            write!(*s, "<{}>{}</{}>{}", tag, xml_escape(text)?, tag, eol)?;
         }
+        Ok(())
     }
 
-    //  Use SL "nan", not Rust "NaN"
-    fn f64_to_xml(v: f64) -> String {
-        let ss = v.to_string();
-        if ss == "NaN" {
-            "nan".to_string()
-        } else {
-            ss
-        }
-    }
+    let eol = options.line_ending.as_str();
+
     //  Emit XML for all possible types.
     match val {
-        LLSDValue::Undefined => tag_value(s, "undef", "", indent),
-        LLSDValue::Boolean(v) => tag_value(s, "boolean", if *v { "true" } else { "false" }, indent),
-        LLSDValue::String(v) => tag_value(s, "string", v.as_str(), indent),
-        LLSDValue::URI(v) => tag_value(s, "uri", v.as_str(), indent),
-        LLSDValue::Integer(v) => tag_value(s, "integer", v.to_string().as_str(), indent),
-        LLSDValue::Real(v) => tag_value(s, "real", f64_to_xml(*v).as_str(), indent),
-        LLSDValue::UUID(v) => tag_value(s, "uuid", v.to_string().as_str(), indent),
-        LLSDValue::Binary(v) => tag_value(s, "binary", base64::encode(v).as_str(), indent),
-        LLSDValue::Date(v) => tag_value(
-            s,
-            "date",
-            &chrono::Utc
-                .timestamp(*v, 0)
-                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
-            indent,
-        ),
+        LLSDValue::Undefined => tag_value(s, "undef", "", indent, eol)?,
+        LLSDValue::Boolean(v) => {
+            tag_value(s, "boolean", if *v { "true" } else { "false" }, indent, eol)?
+        }
+        LLSDValue::String(v) => tag_value(s, "string", v.as_str(), indent, eol)?,
+        LLSDValue::URI(v) => tag_value(s, "uri", v.as_str(), indent, eol)?,
+        LLSDValue::Integer(v) => tag_value(s, "integer", v.to_string().as_str(), indent, eol)?,
+        LLSDValue::Real(v) => {
+            //  Original text, if the value it represents is still the same.
+            let original = real_text.and_then(|r| r.get(path)).filter(|text| {
+                match (if text.to_lowercase() == "nan" {
+                    "NaN"
+                } else {
+                    text
+                })
+                .parse::<f64>()
+                {
+                    Ok(old) => old == *v || (old.is_nan() && v.is_nan()),
+                    Err(_) => false,
+                }
+            });
+            match original {
+                Some(text) => tag_value(s, "real", text, indent, eol)?,
+                None => tag_value(
+                    s,
+                    "real",
+                    format_real(*v, options.real_format).as_str(),
+                    indent,
+                    eol,
+                )?,
+            }
+        }
+        LLSDValue::UUID(v) => tag_value(s, "uuid", v.to_string().as_str(), indent, eol)?,
+        LLSDValue::Binary(v) => tag_value(s, "binary", base64::encode(v).as_str(), indent, eol)?,
+        LLSDValue::Date(v) => tag_value(s, "date", &format_date(*v)?, indent, eol)?,
         LLSDValue::Map(v) => {
-            tag(s, "map", false, indent);
-            for (key, value) in v {
-                tag_value(s, "key", key, indent + spaces);
-                generate_value(s, value, spaces, indent + spaces);
+            tag(s, "map", false, indent, eol)?;
+            for (key, value) in sorted_map_entries(v) {
+                tag_value(s, "key", key, indent + spaces, eol)?;
+                let keypath = match real_text {
+                    Some(_) => format!("{}/{}", path, escape_path_key(key)),
+                    None => String::new(),
+                };
+                generate_value(
+                    s,
+                    value,
+                    spaces,
+                    indent + spaces,
+                    options,
+                    real_text,
+                    &keypath,
+                )?;
             }
-            tag(s, "map", true, indent);
+            tag(s, "map", true, indent, eol)?;
         }
         LLSDValue::Array(v) => {
-            tag(s, "array", false, indent);
-            for value in v {
-                generate_value(s, value, spaces, indent + spaces);
+            tag(s, "array", false, indent, eol)?;
+            for (n, value) in v.iter().enumerate() {
+                let itempath = match real_text {
+                    Some(_) => format!("{}/{}", path, n),
+                    None => String::new(),
+                };
+                generate_value(
+                    s,
+                    value,
+                    spaces,
+                    indent + spaces,
+                    options,
+                    real_text,
+                    &itempath,
+                )?;
             }
-            tag(s, "array", true, indent);
+            tag(s, "array", true, indent, eol)?;
         }
     };
+    Ok(())
 }
 
 /// XML standard character escapes.
-fn xml_escape(unescaped: &str) -> String {
+/// Whitespace at either end is written as character references, so that
+/// the parser, which trims text, keeps it. Carriage returns are also written
+/// that way, since XML parsers turn CR LF into LF. Characters which XML 1.0
+/// cannot represent at all, such as most control characters, are an error.
+pub(crate) fn xml_escape(unescaped: &str) -> Result<String, Error> {
+    let inner = unescaped.trim();
+    let start = unescaped.len() - unescaped.trim_start().len(); // byte offset of inner text
     let mut s = String::new();
-    for ch in unescaped.chars() {
+    for (pos, ch) in unescaped.char_indices() {
+        let outside = pos < start || pos >= start + inner.len();
         match ch {
             '<' => s += "&lt;",
             '>' => s += "&gt;",
             '\'' => s += "&apos;",
             '&' => s += "&amp;",
             '"' => s += "&quot;",
+            '\r' => s += "&#xD;",
+            '\t' | '\n' if outside => s += &format!("&#x{:X};", ch as u32),
+            '\t' | '\n' => s.push(ch),
+            //  Checked before edge whitespace, since VT and FF are whitespace too.
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {
+                return Err(UnrepresentableInXml {
+                    ch,
+                    text: unescaped.to_string(),
+                }
+                .into())
+            }
+            _ if ch.is_whitespace() && outside => s += &format!("&#x{:X};", ch as u32),
             _ => s.push(ch),
         }
     }
-    s
+    Ok(s)
 }
 
 // Unit tests
@@ -602,3 +1353,650 @@ fn xmlparsetest1() {
         assert_eq!(s1, s2);
     }
 }
+
+#[test]
+fn xmlbase85test() {
+    //  Base 85 binary split across lines, with indentation.
+    const TESTXML85: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<binary encoding="base85">87cURD]j7BEbo8;
+    +EV:2F!,1&lt;+C\c#
+    AKWri/c</binary>
+</llsd>
+"#;
+    let parsed = parse(TESTXML85).unwrap();
+    assert_eq!(
+        LLSDValue::Binary(b"Hello world, this is base 85.".to_vec()),
+        parsed
+    );
+}
+
+#[test]
+fn xmlpeektypetest() {
+    let map = LLSDValue::Map(HashMap::new());
+    let array = LLSDValue::Array(vec![LLSDValue::Integer(1)]);
+    assert_eq!(
+        "map",
+        peek_type(&to_xml_string(&map, true).unwrap()).unwrap()
+    );
+    assert_eq!(
+        "array",
+        peek_type(&to_xml_string(&array, false).unwrap()).unwrap()
+    );
+    assert_eq!(
+        "integer",
+        peek_type("<?xml version=\"1.0\" ?><!-- c --><llsd><integer>5</integer></llsd>").unwrap()
+    );
+    assert!(peek_type("<?xml version=\"1.0\" ?><llsd></llsd>").is_err());
+    assert!(peek_type("<?xml version=\"1.0\" ?><llsd><complex>2i</complex></llsd>").is_err());
+}
+
+#[test]
+fn xmldatetest() {
+    //  A date with fractional seconds must survive all the formats.
+    const TESTXMLDATE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<date>2006-02-01T14:29:53.43Z</date>
+</llsd>
+"#;
+    let parsed = parse(TESTXMLDATE).unwrap();
+    assert_eq!(LLSDValue::Date(1138804193.43), parsed);
+    let generated = to_xml_string(&parsed, true).unwrap();
+    println!("Generated: {}", generated);
+    assert!(generated.contains("<date>2006-02-01T14:29:53.430Z</date>"));
+    assert_eq!(parsed, parse(&generated).unwrap());
+    let bin = crate::binary::to_bytes(&parsed).unwrap();
+    assert_eq!(parsed, LLSDValue::parse(&bin).unwrap());
+    let notation = crate::notation::to_string(&parsed).unwrap();
+    assert_eq!(parsed, LLSDValue::parse(notation.as_bytes()).unwrap());
+    //  Whole seconds are written without a fraction.
+    let whole = to_xml_string(&LLSDValue::Date(1138804193.0), false).unwrap();
+    assert!(whole.contains("<date>2006-02-01T14:29:53Z</date>"));
+}
+
+#[test]
+fn xmldateboundarytest() {
+    //  Dates before 1970 are negative. Must round-trip in the text formats.
+    let cases = [
+        ("1969-12-31T23:59:59Z", -1.0),
+        ("1969-12-31T23:59:59.500Z", -0.5),
+        ("1970-01-01T00:00:00Z", 0.0),
+        ("1900-01-01T00:00:00Z", -2208988800.0),
+        ("0000-01-01T00:00:00Z", -62167219200.0),
+        ("9999-12-31T23:59:59.999Z", 253402300799.999),
+    ];
+    for (text, secs) in cases.iter() {
+        assert_eq!(*secs, parse_date(text).unwrap());
+        assert_eq!(*text, format_date(*secs).unwrap());
+        let val = LLSDValue::Date(*secs);
+        let xml = to_xml_string(&val, false).unwrap();
+        assert_eq!(val, parse(&xml).unwrap());
+        let notation = crate::notation::to_string(&val).unwrap();
+        assert_eq!(val, LLSDValue::parse(notation.as_bytes()).unwrap());
+    }
+    //  Past year 9999, and non-finite, cannot be written as RFC 3339.
+    assert!(date_in_range(253402300799.0));
+    assert!(!date_in_range(253402300800.0));
+    assert!(!date_in_range(-62167219201.0));
+    assert!(!date_in_range(1.0e300));
+    assert!(!date_in_range(f64::NAN));
+    assert!(!date_in_range(f64::NEG_INFINITY));
+}
+
+#[test]
+fn xmlinvaliddatetest() {
+    //  Must be an error, not a panic, in every text format.
+    let val = LLSDValue::Array(vec![LLSDValue::Date(i64::MAX as f64)]);
+    let err = to_xml_string(&val, true).unwrap_err();
+    println!("Invalid date: {}", err);
+    assert_eq!(
+        Some(&InvalidDate {
+            value: i64::MAX as f64
+        }),
+        err.downcast_ref::<InvalidDate>()
+    );
+    let err = crate::notation::to_string(&val).unwrap_err();
+    assert!(err.downcast_ref::<InvalidDate>().is_some());
+    assert!(to_xml_string(&LLSDValue::Date(f64::NAN), false).is_err());
+    //  Binary keeps any f64.
+    let bin = crate::binary::to_bytes(&val).unwrap();
+    assert_eq!(val, LLSDValue::parse(&bin).unwrap());
+}
+
+#[test]
+fn xmlsortedkeystest() {
+    //  Same map, built in different orders, must generate the same XML.
+    let keys = ["delta", "alpha", "charlie", "bravo", "echo"];
+    let mut map1 = HashMap::new();
+    for (n, key) in keys.iter().enumerate() {
+        map1.insert(key.to_string(), LLSDValue::Integer(n as i32));
+    }
+    let mut map2 = HashMap::with_capacity(100);
+    for (n, key) in keys.iter().enumerate().rev() {
+        map2.insert(key.to_string(), LLSDValue::Integer(n as i32));
+    }
+    let xml1 = to_xml_string(&LLSDValue::Map(map1), true).unwrap();
+    let xml2 = to_xml_string(&LLSDValue::Map(map2), true).unwrap();
+    println!("Sorted: {}", xml1);
+    assert_eq!(xml1, xml2);
+    let positions: Vec<usize> = ["alpha", "bravo", "charlie", "delta", "echo"]
+        .iter()
+        .map(|key| xml1.find(key).unwrap())
+        .collect();
+    assert!(positions.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[test]
+fn xmltruncatedtest() {
+    //  Document truncated after the value but before </llsd>.
+    const TESTXMLTRUNCATED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+  <key>scale</key>
+    <string>one minute</string>
+</map>
+"#;
+    let err = parse(TESTXMLTRUNCATED).unwrap_err();
+    println!("Error as expected: {:?}", err);
+    assert!(err.to_string().contains("missing </llsd>"));
+    //  Same document, complete.
+    assert!(parse(&format!("{}</llsd>\n", TESTXMLTRUNCATED)).is_ok());
+}
+
+#[test]
+fn xmlparsealltest() {
+    let docs: String = (1..=3)
+        .map(|n| to_xml_string(&LLSDValue::Integer(n), true).unwrap() + "\n")
+        .collect();
+    println!("Concatenated: {}", docs);
+    assert_eq!(
+        vec![
+            LLSDValue::Integer(1),
+            LLSDValue::Integer(2),
+            LLSDValue::Integer(3)
+        ],
+        parse_all(&docs).unwrap()
+    );
+    //  Single-document parse stays strict.
+    assert!(parse(&docs).is_err());
+}
+
+#[test]
+fn xmllenientintegertest() {
+    fn parse_integer_doc(text: &str, options: &ParseOptions) -> Result<LLSDValue, Error> {
+        parse_with_options(
+            &format!(
+                "<?xml version=\"1.0\" ?><llsd><integer>{}</integer></llsd>",
+                text
+            ),
+            options,
+        )
+    }
+    let strict = ParseOptions::default();
+    let lenient = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        LLSDValue::Integer(5),
+        parse_integer_doc("+5", &lenient).unwrap()
+    );
+    assert_eq!(
+        LLSDValue::Integer(16),
+        parse_integer_doc("0x10", &lenient).unwrap()
+    );
+    assert_eq!(
+        LLSDValue::Integer(-31),
+        parse_integer_doc("-0x1F", &lenient).unwrap()
+    );
+    assert_eq!(
+        LLSDValue::Integer(5),
+        parse_integer_doc("0b101", &lenient).unwrap()
+    );
+    assert!(parse_integer_doc("0x10", &strict).is_err());
+    assert!(parse_integer_doc("0b101", &strict).is_err());
+    assert!(parse_integer_doc("0x1ffffffff", &lenient).is_err()); // too big
+    assert!(parse_integer_doc("twelve", &lenient).is_err());
+}
+
+#[test]
+fn xmlmapentrycommenttest() {
+    const TESTXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+  <key>region_id</key>
+  <!-- comment between key and value -->
+  <uuid>67153d5b-3659-afb4-8510-adda2c034649</uuid>
+  <key>scale</key><!-- another --><string>one minute</string>
+</map>
+</llsd>
+"#;
+    let parsed = parse(TESTXML).unwrap();
+    assert_eq!(
+        Some(&LLSDValue::String("one minute".to_string())),
+        parsed.as_map().unwrap().get("scale")
+    );
+    //  Key with no value.
+    const BADXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd><map><key>scale</key><!-- nothing --></map></llsd>
+"#;
+    let err = parse(BADXML).unwrap_err().to_string();
+    println!("Error as expected: {}", err);
+    assert!(err.contains("Map key \"scale\" has no value"));
+}
+
+#[test]
+fn xmlpreserverealstest() {
+    const TESTXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+  <key>scale</key><real>0.10</real>
+  <key>a/b</key><array><real>1.50</real><real>2e3</real></array>
+</map>
+</llsd>
+"#;
+    let (mut parsed, real_text) = parse_preserving_reals(TESTXML).unwrap();
+    println!("Real text: {:?}", real_text);
+    assert_eq!(Some(&"0.10".to_string()), real_text.get("/scale"));
+    assert_eq!(Some(&"2e3".to_string()), real_text.get("/a~1b/1"));
+    let generated = to_xml_string_preserving_reals(&parsed, false, &real_text).unwrap();
+    println!("Generated: {}", generated);
+    assert!(generated.contains("<real>0.10</real>"));
+    assert!(generated.contains("<real>1.50</real>"));
+    assert!(generated.contains("<real>2e3</real>"));
+    //  Ordinary output normalizes.
+    assert!(to_xml_string(&parsed, false)
+        .unwrap()
+        .contains("<real>0.1</real>"));
+    //  A changed value is written in the usual way.
+    *parsed.pointer_mut("/scale").unwrap() = LLSDValue::Real(0.25);
+    let generated = to_xml_string_preserving_reals(&parsed, false, &real_text).unwrap();
+    assert!(generated.contains("<real>0.25</real>"));
+    assert!(generated.contains("<real>1.50</real>"));
+}
+
+#[test]
+fn xmlbooleantest() {
+    //  Legacy <bool> tag, and all the spellings of true and false.
+    const TESTXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<array><bool>1</bool><boolean>True</boolean><boolean> f </boolean><bool>0.0</bool></array>
+</llsd>
+"#;
+    let parsed = parse(TESTXML).unwrap();
+    assert_eq!(
+        LLSDValue::Array(vec![
+            LLSDValue::Boolean(true),
+            LLSDValue::Boolean(true),
+            LLSDValue::Boolean(false),
+            LLSDValue::Boolean(false)
+        ]),
+        parsed
+    );
+    assert!(parse("<?xml version=\"1.0\"?><llsd><boolean>yes</boolean></llsd>").is_err());
+    assert_eq!(
+        "boolean",
+        peek_type(TESTXML.replace("<array>", "").as_str()).unwrap()
+    );
+}
+
+#[test]
+fn xmllenientrealtest() {
+    fn parse_real_doc(text: &str, options: &ParseOptions) -> Result<LLSDValue, Error> {
+        parse_with_options(
+            &format!("<?xml version=\"1.0\" ?><llsd><real>{}</real></llsd>", text),
+            options,
+        )
+    }
+    let strict = ParseOptions::default();
+    let lenient = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    assert!(parse_real_doc("1,5", &strict).is_err());
+    assert_eq!(
+        LLSDValue::Real(1.5),
+        parse_real_doc("1,5", &lenient).unwrap()
+    );
+    assert_eq!(
+        LLSDValue::Real(-0.25),
+        parse_real_doc("-0,25", &lenient).unwrap()
+    );
+    assert!(parse_real_doc("1,000.5", &lenient).is_err()); // thousands separator, not decimal comma
+    assert!(parse_real_doc("1,2,3", &lenient).is_err());
+    assert_eq!(
+        LLSDValue::Real(2.5),
+        parse_real_doc("2.5", &strict).unwrap()
+    );
+}
+
+#[test]
+fn xmlmapkeynovaluetest() {
+    //  Final key of a map has no value.
+    const BADXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd><map><key>a</key><integer>1</integer><key>b</key></map></llsd>
+"#;
+    let err = parse(BADXML).unwrap_err().to_string();
+    println!("Error as expected: {}", err);
+    assert!(err.contains("Map key \"b\" has no value, found </map>"));
+    //  Document ends after the key.
+    let err = parse(&BADXML[..BADXML.find("</map>").unwrap()])
+        .unwrap_err()
+        .to_string();
+    println!("Error as expected: {}", err);
+    assert!(err.contains("Map key \"b\" has no value, unexpected end of data"));
+}
+
+#[test]
+fn xmlwriterroundtriptest() {
+    //  Every variant, and strings which need escaping, survive writing and parsing.
+    let strings = [
+        "",
+        "plain",
+        "<tag> & \"quotes\" 'apos'",
+        "  leading and trailing  ",
+        "\ttabs\nand\r\nnewlines\n",
+        "Unicode \u{e9}\u{1f600}",
+        "]]>",
+    ];
+    let mut items: Vec<LLSDValue> = strings
+        .iter()
+        .map(|s| LLSDValue::String(s.to_string()))
+        .collect();
+    items.extend(vec![
+        LLSDValue::Undefined,
+        LLSDValue::Boolean(true),
+        LLSDValue::Boolean(false),
+        LLSDValue::Integer(i32::MIN),
+        LLSDValue::Real(-1.0e-300),
+        LLSDValue::Real(f64::INFINITY),
+        LLSDValue::UUID(uuid::Uuid::parse_str("67153d5b-3659-afb4-8510-adda2c034649").unwrap()),
+        LLSDValue::Date(1138804193.5),
+        LLSDValue::URI("http://example.com/a?b=1&c=<2>".to_string()),
+        LLSDValue::Binary(vec![0, 1, 2, 255]),
+        LLSDValue::Binary(Vec::new()),
+        LLSDValue::Map(
+            [
+                (" spaced key ".to_string(), LLSDValue::Integer(1)),
+                ("a&b".to_string(), LLSDValue::Array(Vec::new())),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        ),
+    ]);
+    let val = LLSDValue::Array(items);
+    for indent in &[false, true] {
+        let generated = to_xml_string(&val, *indent).unwrap();
+        println!("Generated: {}", generated);
+        assert_eq!(val, parse(&generated).unwrap());
+    }
+    //  NaN does not compare equal, so check it separately.
+    let nan = parse(&to_xml_string(&LLSDValue::Real(f64::NAN), false).unwrap()).unwrap();
+    assert!(nan.as_real().unwrap().is_nan());
+    //  Control characters cannot be written in XML 1.0.
+    assert!(to_xml_string(&LLSDValue::String("bell\u{7}".to_string()), false).is_err());
+}
+
+#[test]
+fn xmlcdatatest() {
+    const TESTXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+  <key><![CDATA[a&b]]></key><string><![CDATA[raw & <data> &amp; ]]></string>
+  <key>n</key><real><![CDATA[1.5]]></real>
+</map>
+</llsd>
+"#;
+    let parsed = parse(TESTXML).unwrap();
+    println!("Parsed: {:?}", parsed);
+    assert_eq!(
+        Some(&LLSDValue::String("raw & <data> &amp; ".to_string())),
+        parsed.pointer("/a&b")
+    );
+    assert_eq!(Some(&LLSDValue::Real(1.5)), parsed.pointer("/n"));
+    let (_, real_text) = parse_preserving_reals(TESTXML).unwrap();
+    assert_eq!(Some(&"1.5".to_string()), real_text.get("/n"));
+    //  CDATA runs together with the text on either side.
+    const MIXED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+  <key>x<![CDATA[&]]>y</key><string>a<![CDATA[&]]>b</string>
+  <key>n</key><real>1<![CDATA[.]]>5</real>
+  <key>c</key><string>a<!-- note -->b</string>
+</map>
+</llsd>
+"#;
+    let (parsed, real_text) = parse_preserving_reals(MIXED).unwrap();
+    assert_eq!(
+        Some(&LLSDValue::String("a&b".to_string())),
+        parsed.pointer("/x&y")
+    );
+    assert_eq!(Some(&LLSDValue::Real(1.5)), parsed.pointer("/n"));
+    assert_eq!(Some(&"1.5".to_string()), real_text.get("/n"));
+    //  Text split by a comment is still joined by a space.
+    assert_eq!(
+        Some(&LLSDValue::String("a b".to_string())),
+        parsed.pointer("/c")
+    );
+}
+
+#[test]
+fn xmlmultiplerootvaluestest() {
+    const TWOVALUES: &str =
+        "<?xml version=\"1.0\"?><llsd><integer>1</integer><string>x</string></llsd>";
+    let err = parse(TWOVALUES).unwrap_err();
+    println!("Two values: {}", err);
+    assert_eq!(
+        "string",
+        err.downcast_ref::<MultipleRootValues>().unwrap().tag
+    );
+    //  Comments after the value are fine.
+    const COMMENTED: &str = "<?xml version=\"1.0\"?><llsd><integer>1</integer><!-- done --></llsd>";
+    assert_eq!(LLSDValue::Integer(1), parse(COMMENTED).unwrap());
+    //  Each of several <llsd> blocks is checked too.
+    let twoblocks = format!("{}{}", COMMENTED, TWOVALUES);
+    assert!(parse_all(&twoblocks).is_err());
+}
+
+#[test]
+fn xmlkeyattributestest() {
+    //  Attributes on <key> mean nothing in LLSD, and are ignored.
+    const TESTXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+    <key type="string" xml:lang="en">region name</key>
+    <string>Vallone</string>
+    <key id='2'>agents</key>
+    <integer>3</integer>
+</map>
+</llsd>"#;
+    let parsed = parse(TESTXML).unwrap();
+    println!("Parsed: {:?}", parsed);
+    assert_eq!(
+        Some(&LLSDValue::String("Vallone".to_string())),
+        parsed.pointer("/region name")
+    );
+    assert_eq!(Some(&LLSDValue::Integer(3)), parsed.pointer("/agents"));
+    assert_eq!(2, parsed.as_map().unwrap().len());
+}
+
+#[test]
+fn xmlrealformattest() {
+    let third = LLSDValue::Real(1.0 / 3.0);
+    let xml = |real_format| {
+        to_xml_string_with_options(
+            &third,
+            false,
+            &XmlOptions {
+                real_format,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    assert!(xml(RealFormat::Shortest).contains("<real>0.3333333333333333</real>"));
+    assert_eq!(
+        to_xml_string(&third, false).unwrap(),
+        xml(RealFormat::Shortest)
+    );
+    assert!(xml(RealFormat::Fixed(3)).contains("<real>0.333</real>"));
+    assert!(xml(RealFormat::Significant(4)).contains("<real>0.3333</real>"));
+    //  Shortest reads back exactly.
+    assert_eq!(third, parse(&xml(RealFormat::Shortest)).unwrap());
+    //  Other values
+    assert_eq!("123000", format_real(123456.0, RealFormat::Significant(3)));
+    assert_eq!(
+        "0.00123",
+        format_real(0.0012345, RealFormat::Significant(3))
+    );
+    assert_eq!("-2.50", format_real(-2.5, RealFormat::Fixed(2)));
+    assert_eq!("2", format_real(2.0, RealFormat::Fixed(0)));
+    assert_eq!("nan", format_real(f64::NAN, RealFormat::Fixed(2)));
+}
+
+#[test]
+fn xmllineendingbomtest() {
+    let val = crate::notation::parse(b"{'a':[i1,'two']}").unwrap();
+    //  Default is LF, no BOM.
+    let plain = to_xml_string_with_options(&val, true, &XmlOptions::default()).unwrap();
+    assert!(plain.starts_with("<?xml"));
+    assert!(!plain.contains('\r'));
+    //  Windows style.
+    let options = XmlOptions {
+        line_ending: LineEnding::CrLf,
+        emit_bom: true,
+        ..Default::default()
+    };
+    let windows = to_xml_string_with_options(&val, true, &options).unwrap();
+    println!("CRLF: {:?}", windows);
+    assert!(windows.as_bytes().starts_with(b"\xEF\xBB\xBF<?xml"));
+    assert_eq!(plain.matches('\n').count(), windows.matches("\r\n").count());
+    assert!(!windows.replace("\r\n", "").contains('\n'));
+    assert!(windows.contains("<llsd>\r\n"));
+    assert!(windows.contains("<integer>1</integer>\r\n"));
+    assert_eq!(
+        plain,
+        windows.trim_start_matches('\u{FEFF}').replace("\r\n", "\n")
+    );
+    assert_eq!(val, LLSDValue::parse(windows.as_bytes()).unwrap());
+}
+
+#[test]
+fn xmlcollectingerrorstest() {
+    const TESTXMLBADVALUES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<llsd>
+<map>
+    <key>good</key><integer>1</integer>
+    <key>speed</key><real>fast</real>
+    <key>list</key>
+    <array>
+        <integer>2</integer>
+        <uuid>not-a-uuid</uuid>
+    </array>
+    <key>good</key><integer>3</integer>
+</map>
+</llsd>
+"#;
+    //  Normal parse stops at the first.
+    assert!(parse(TESTXMLBADVALUES).is_err());
+    let (val, diags) = parse_collecting_errors(TESTXMLBADVALUES).unwrap();
+    println!("Diagnostics: {:?}", diags);
+    assert_eq!(Some(&LLSDValue::Undefined), val.pointer("/speed"));
+    assert_eq!(Some(&LLSDValue::Undefined), val.pointer("/list/1"));
+    assert_eq!(Some(&LLSDValue::Integer(2)), val.pointer("/list/0"));
+    assert_eq!(Some(&LLSDValue::Integer(3)), val.pointer("/good"));
+    let bad_paths: Vec<&str> = diags
+        .iter()
+        .filter_map(|d| match d {
+            Diagnostic::BadValue { path, .. } => Some(path.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(vec!["/speed", "/list/1"], bad_paths);
+    assert!(diags.contains(&Diagnostic::DuplicateKey {
+        key: "good".to_string(),
+        path: "/good".to_string()
+    }));
+    //  Broken structure is still fatal.
+    assert!(parse_collecting_errors(
+        "<?xml version=\"1.0\" ?><llsd><map><real>1</real></map></llsd>"
+    )
+    .is_err());
+}
+
+#[test]
+fn xmlunrepresentabletest() {
+    //  NUL cannot be in XML at all. Must be an error, not broken XML.
+    let val = LLSDValue::String("a\0b".to_string());
+    let err = to_xml_string(&val, false).unwrap_err();
+    println!("Unrepresentable: {}", err);
+    assert_eq!(
+        Some(&UnrepresentableInXml {
+            ch: '\0',
+            text: "a\0b".to_string()
+        }),
+        err.downcast_ref::<UnrepresentableInXml>()
+    );
+    //  Also in map keys and URIs, at any depth.
+    let key = crate::notation::parse(b"{'ok':[{'bad\x01key':i1}]}").unwrap();
+    let err = to_xml_string(&key, true).unwrap_err();
+    assert_eq!(
+        '\u{1}',
+        err.downcast_ref::<UnrepresentableInXml>().unwrap().ch
+    );
+    let uri = LLSDValue::URI("http://example.com/\u{1b}".to_string());
+    assert!(to_xml_string(&uri, false).is_err());
+    //  Binary can hold it.
+    let bin = crate::binary::to_bytes(&val).unwrap();
+    assert_eq!(val, LLSDValue::parse(&bin).unwrap());
+    //  Vertical tab and form feed are whitespace, but forbidden even at the ends.
+    for text in ["\u{b}x", "x\u{c}", "\u{c}", "a\u{b}b"].iter() {
+        let err = to_xml_string(&LLSDValue::String(text.to_string()), false).unwrap_err();
+        assert!(
+            err.downcast_ref::<UnrepresentableInXml>().is_some(),
+            "{:?}",
+            text
+        );
+    }
+    //  Tab, newline, and carriage return are fine.
+    let ws = LLSDValue::String("a\tb\nc\rd".to_string());
+    assert_eq!(ws, parse(&to_xml_string(&ws, false).unwrap()).unwrap());
+}
+
+#[test]
+fn xmlisvalidtest() {
+    let val = crate::notation::parse(b"{'a':[i1,r2.5,'three'],'b':{}}").unwrap();
+    let generated = to_xml_string(&val, true).unwrap();
+    assert!(is_valid(&generated));
+    assert!(!is_valid(&generated[..generated.len() - 3])); // truncated
+    assert!(!is_valid(
+        &generated.replace("<integer>1</integer>", "<integer>one</integer>")
+    ));
+    assert!(!is_valid(&generated.replace("</array>", "</map>")));
+    assert!(!is_valid(""));
+}
+
+#[test]
+fn xmlemptykeytest() {
+    //  An empty key is valid, in both tag forms, and round trips.
+    let val = LLSDValue::Map(
+        [("".to_string(), LLSDValue::Integer(1))]
+            .iter()
+            .cloned()
+            .collect(),
+    );
+    for xml in [
+        "<llsd><map><key></key><integer>1</integer></map></llsd>",
+        "<llsd><map><key/><integer>1</integer></map></llsd>",
+    ]
+    .iter()
+    {
+        assert_eq!(val, parse(xml).unwrap());
+    }
+    let generated = to_xml_string(&val, false).unwrap();
+    assert_eq!(val, parse(&generated).unwrap());
+    //  Same as binary.
+    let bin = crate::binary::to_bytes(&val).unwrap();
+    assert_eq!(val, LLSDValue::parse(&bin).unwrap());
+}
@@ -0,0 +1,63 @@
+//
+//  Library for serializing and de-serializing data in
+//  Linden Lab Structured Data format.
+//
+//  Format documentation is at http://wiki.secondlife.com/wiki/LLSD
+//
+//  Round-trip property tests. parse(serialize(v)) must equal v.
+//
+//  Animats
+//  October, 2026.
+//  License: LGPL.
+//
+use llsd::LLSDValue;
+use proptest::prelude::*;
+use std::collections::HashMap;
+
+/// Any scalar LLSD value.
+fn arb_scalar() -> impl Strategy<Value = LLSDValue> {
+    prop_oneof![
+        Just(LLSDValue::Undefined),
+        any::<bool>().prop_map(LLSDValue::Boolean),
+        any::<i32>().prop_map(LLSDValue::Integer),
+        any::<f64>().prop_map(LLSDValue::Real),
+        any::<u128>().prop_map(|v| LLSDValue::UUID(uuid::Uuid::from_u128(v))),
+        any::<String>().prop_map(LLSDValue::String),
+        any::<String>().prop_map(LLSDValue::URI),
+        //  Dates in years 1 to 9999, to the millisecond, which is what XML keeps.
+        //  Computed the way the XML parser computes them.
+        (-62_135_596_800i64..253_402_300_799i64, 0u32..1000u32).prop_map(|(secs, millis)| {
+            LLSDValue::Date(secs as f64 + f64::from(millis * 1_000_000) / 1.0e9)
+        }),
+        proptest::collection::vec(any::<u8>(), 0..64).prop_map(LLSDValue::Binary),
+    ]
+}
+
+/// Any LLSD value, with bounded nesting.
+fn arb_value() -> impl Strategy<Value = LLSDValue> {
+    arb_scalar().prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            proptest::collection::vec(inner.clone(), 0..8).prop_map(LLSDValue::Array),
+            proptest::collection::hash_map(any::<String>(), inner, 0..8)
+                .prop_map(|m: HashMap<String, LLSDValue>| LLSDValue::Map(m)),
+        ]
+    })
+}
+
+//  LLSDValue equality treats NaN as equal to NaN, so reals compare as expected.
+proptest! {
+    #[test]
+    fn binaryroundtrip(val in arb_value()) {
+        let bytes = llsd::binary::to_bytes(&val).unwrap();
+        prop_assert!(llsd::binary::is_valid(&bytes));
+        let parsed = llsd::binary::parse_array(&bytes[llsd::binary::LLSDBINARYPREFIX.len()..]).unwrap();
+        prop_assert_eq!(val, parsed);
+    }
+
+    #[test]
+    fn xmlroundtrip(val in arb_value()) {
+        let s = llsd::xml::to_xml_string(&val, true).unwrap();
+        let parsed = llsd::xml::parse(&s).unwrap();
+        prop_assert_eq!(val, parsed);
+    }
+}